@@ -0,0 +1,379 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use dialoguer::{Confirm, FuzzySelect, Input, Select};
+use futures::{future::LocalBoxFuture, FutureExt};
+use helixlauncher_core::launch::instance;
+
+use crate::layer::{meta, Condition, LaunchConfig, Layer, SetupContext};
+
+const MODLOADERS: [instance::Modloader; 4] = [
+    instance::Modloader::Vanilla,
+    instance::Modloader::Quilt,
+    instance::Modloader::Fabric,
+    instance::Modloader::Forge,
+];
+
+const LAYER_TYPES: [&str; 9] = [
+    "Done",
+    "Minecraft instance",
+    "Directory overlay",
+    "Execute command",
+    "Launch config",
+    "Modrinth mod",
+    "Variants",
+    "Conditional",
+    "Other layer as json",
+];
+
+/// The result of prompting for the type and content of a layer
+enum LayerChoice {
+    Done,
+    /// A prompt was cancelled or the entered layer was rejected, so the type is asked for again
+    Retry,
+    Layer(Layer),
+}
+
+/// Prompts for layers until `Done` is selected
+pub async fn prompt_layers(context: &SetupContext) -> Result<Vec<Layer>> {
+    collect_layers(|| prompt_layer(context, "Add layer")).await
+}
+
+/// Collects the layers chosen by `prompt` until it returns `Done`
+async fn collect_layers<'a>(
+    mut prompt: impl FnMut() -> LocalBoxFuture<'a, Result<LayerChoice>>,
+) -> Result<Vec<Layer>> {
+    let mut layers = vec![];
+    while let Some(layer) = next_layer(&mut prompt).await? {
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+/// Prompts until a layer is chosen, `None` if `Done` was selected
+async fn next_layer<'a>(
+    mut prompt: impl FnMut() -> LocalBoxFuture<'a, Result<LayerChoice>>,
+) -> Result<Option<Layer>> {
+    loop {
+        match prompt().await? {
+            LayerChoice::Done => return Ok(None),
+            LayerChoice::Retry => continue,
+            LayerChoice::Layer(layer) => return Ok(Some(layer)),
+        }
+    }
+}
+
+/// The variants layer of the given alternatives, of which there has to be at least one
+fn variants_layer(alternatives: Vec<Layer>) -> Result<Layer> {
+    ensure!(
+        !alternatives.is_empty(),
+        "Variants need at least one alternative"
+    );
+    Ok(Layer::Variants(alternatives))
+}
+
+/// Prompts for the type and content of a layer once
+fn prompt_layer<'a>(
+    context: &'a SetupContext,
+    prompt: &'a str,
+) -> LocalBoxFuture<'a, Result<LayerChoice>> {
+    async move {
+        let Some(index) = Select::new()
+            .with_prompt(prompt)
+            .items(&LAYER_TYPES)
+            .default(0)
+            .interact_opt()
+            .context("Unable to prompt layer type")?
+        else {
+            return Ok(LayerChoice::Retry);
+        };
+        let layer = match LAYER_TYPES[index] {
+            "Done" => return Ok(LayerChoice::Done),
+            "Minecraft instance" => prompt_instance(context).await?,
+            "Directory overlay" => Some(Layer::DirectoryOverlay {
+                source: PathBuf::from(prompt_text(
+                    "Overlay directory, relative to the variant directory",
+                )?),
+            }),
+            "Execute command" => Some(Layer::ExecuteCommand(prompt_text("Command")?)),
+            "Launch config" => Some(Layer::LaunchConfig(prompt_launch_config()?)),
+            "Modrinth mod" => Some(Layer::ModrinthMod {
+                project: prompt_text("Modrinth project id or slug")?,
+                version: None,
+                version_range: None,
+                resolve_dependencies: Confirm::new()
+                    .with_prompt("Install required dependencies?")
+                    .default(true)
+                    .interact()
+                    .context("Unable to prompt dependency resolution")?,
+            }),
+            "Variants" => {
+                let alternatives =
+                    collect_layers(|| prompt_layer(context, "Add alternative")).await?;
+                match variants_layer(alternatives) {
+                    Ok(layer) => Some(layer),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        None
+                    }
+                }
+            }
+            "Conditional" => prompt_conditional(context).await?,
+            "Other layer as json" => {
+                let json: String = Input::new()
+                    .with_prompt("Layer")
+                    .validate_with(|it: &String| {
+                        serde_json::from_str::<Layer>(it)
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    })
+                    .interact_text()
+                    .context("Unable to prompt layer")?;
+                Some(serde_json::from_str(&json)?)
+            }
+            _ => None,
+        };
+        Ok(layer.map_or(LayerChoice::Retry, LayerChoice::Layer))
+    }
+    .boxed_local()
+}
+
+fn prompt_text(prompt: &str) -> Result<String> {
+    Input::new()
+        .with_prompt(prompt)
+        .interact_text()
+        .context(format!("Unable to prompt {}", prompt.to_lowercase()))
+}
+
+fn prompt_optional(prompt: &str) -> Result<Option<String>> {
+    let text: String = Input::new()
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .interact_text()
+        .context(format!("Unable to prompt {}", prompt.to_lowercase()))?;
+    Ok(Some(text).filter(|it| !it.is_empty()))
+}
+
+/// Picks the game version from the version manifest and the loader version from the ones available for it.
+/// Falls back to entering them if the lists can't be fetched
+async fn prompt_instance(context: &SetupContext) -> Result<Option<Layer>> {
+    let version = match meta::VersionManifest::get(context).await {
+        Ok(manifest) => {
+            let snapshots = Confirm::new()
+                .with_prompt("Include snapshots?")
+                .default(false)
+                .interact()
+                .context("Unable to prompt snapshot inclusion")?;
+            let versions: Vec<&String> = manifest
+                .versions
+                .iter()
+                .filter(|it| snapshots || it.version_type == "release")
+                .map(|it| &it.id)
+                .collect();
+            let Some(index) = FuzzySelect::new()
+                .with_prompt("Minecraft version")
+                .items(&versions)
+                .default(0)
+                .interact_opt()
+                .context("Unable to prompt minecraft version")?
+            else {
+                return Ok(None);
+            };
+            versions[index].clone()
+        }
+        Err(e) => {
+            eprintln!("Unable to list minecraft versions: {e:#}");
+            prompt_text("Minecraft version")?
+        }
+    };
+    let Some(index) = FuzzySelect::new()
+        .with_prompt("Mod loader")
+        .items(&MODLOADERS)
+        .default(0)
+        .interact_opt()
+        .context("Unable to prompt mod loader")?
+    else {
+        return Ok(None);
+    };
+    let loader = MODLOADERS[index];
+    let loader_version = match loader {
+        instance::Modloader::Vanilla => None,
+        loader => Some(
+            match meta::loader_versions(context, loader, &version).await {
+                Ok(available) if !available.is_empty() => {
+                    let items: Vec<String> = available
+                        .iter()
+                        .map(|(version, stable)| match stable {
+                            true => version.clone(),
+                            false => format!("{version} (unstable)"),
+                        })
+                        .collect();
+                    let Some(index) = FuzzySelect::new()
+                        .with_prompt(format!("{loader} version"))
+                        .items(&items)
+                        .default(0)
+                        .interact_opt()
+                        .context("Unable to prompt loader version")?
+                    else {
+                        return Ok(None);
+                    };
+                    available[index].0.clone()
+                }
+                Ok(_) => {
+                    eprintln!("No {loader} versions are listed for minecraft {version}");
+                    prompt_text(&format!("{loader} version"))?
+                }
+                Err(e) => {
+                    eprintln!("Unable to list {loader} versions: {e:#}");
+                    prompt_text(&format!("{loader} version"))?
+                }
+            },
+        ),
+    };
+    Ok(Some(Layer::Instance {
+        version,
+        loader,
+        loader_version,
+    }))
+}
+
+fn prompt_launch_config() -> Result<LaunchConfig> {
+    Ok(LaunchConfig {
+        min_memory: prompt_optional("Initial heap size, e.g. 512M (optional)")?,
+        max_memory: prompt_optional("Maximum heap size, e.g. 4G (optional)")?,
+        jvm_args: prompt_optional("Jvm arguments, separated by spaces (optional)")?
+            .map(|it| it.split_whitespace().map(str::to_owned).collect())
+            .unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+fn prompt_condition() -> Result<Option<Condition>> {
+    let kinds = [
+        "Mod loader is",
+        "Game version matches",
+        "Preceded by layer type",
+        "Matrix axis has value",
+    ];
+    let Some(index) = Select::new()
+        .with_prompt("Condition")
+        .items(&kinds)
+        .default(0)
+        .interact_opt()
+        .context("Unable to prompt condition")?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(match index {
+        0 => {
+            let Some(index) = FuzzySelect::new()
+                .with_prompt("Mod loader")
+                .items(&MODLOADERS)
+                .default(0)
+                .interact_opt()
+                .context("Unable to prompt mod loader")?
+            else {
+                return Ok(None);
+            };
+            Condition::Loader(MODLOADERS[index])
+        }
+        1 => Condition::GameVersion(prompt_text("Game version requirement, e.g. >=1.20")?),
+        2 => Condition::HasLayer(prompt_text("Layer type, e.g. modrinth_mod")?),
+        _ => Condition::Axis {
+            axis: prompt_text("Axis")?,
+            value: prompt_text("Value")?,
+        },
+    }))
+}
+
+async fn prompt_conditional(context: &SetupContext) -> Result<Option<Layer>> {
+    let Some(condition) = prompt_condition()? else {
+        return Ok(None);
+    };
+    let Some(then) =
+        next_layer(|| prompt_layer(context, "Layer applied if the condition holds")).await?
+    else {
+        return Ok(None);
+    };
+    let otherwise = match Confirm::new()
+        .with_prompt("Add a layer applied otherwise?")
+        .default(false)
+        .interact()
+        .context("Unable to prompt else layer")?
+    {
+        true => next_layer(|| prompt_layer(context, "Layer applied otherwise"))
+            .await?
+            .map(Box::new),
+        false => None,
+    };
+    Ok(Some(Layer::When {
+        condition,
+        then: Box::new(then),
+        otherwise,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::{collect_layers, next_layer, variants_layer, LayerChoice};
+    use crate::layer::Layer;
+
+    fn command(command: &str) -> Layer {
+        Layer::ExecuteCommand(command.to_owned())
+    }
+
+    #[tokio::test]
+    async fn asks_again_after_cancelled_prompts() {
+        let mut choices = vec![
+            LayerChoice::Retry,
+            LayerChoice::Layer(command("first")),
+            LayerChoice::Retry,
+            LayerChoice::Retry,
+            LayerChoice::Layer(command("second")),
+            LayerChoice::Done,
+            LayerChoice::Layer(command("unused")),
+        ]
+        .into_iter();
+        let layers = collect_layers(|| {
+            let choice = choices.next().unwrap();
+            async move { Ok(choice) }.boxed_local()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(layers).unwrap(),
+            serde_json::to_value([command("first"), command("second")]).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(command("unused")).unwrap(),
+            serde_json::to_value(match choices.next() {
+                Some(LayerChoice::Layer(layer)) => layer,
+                _ => panic!("prompted after done"),
+            })
+            .unwrap()
+        );
+
+        let mut choices = vec![LayerChoice::Retry, LayerChoice::Done].into_iter();
+        let layer = next_layer(|| {
+            let choice = choices.next().unwrap();
+            async move { Ok(choice) }.boxed_local()
+        })
+        .await
+        .unwrap();
+        assert!(layer.is_none());
+    }
+
+    #[test]
+    fn rejects_empty_variants() {
+        assert_eq!(
+            variants_layer(vec![]).err().unwrap().to_string(),
+            "Variants need at least one alternative"
+        );
+        assert!(matches!(
+            variants_layer(vec![command("only")]).unwrap(),
+            Layer::Variants(alternatives) if alternatives.len() == 1
+        ));
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use helixlauncher_core::launch::instance;
+use serde::Serialize;
+use serde_json::json;
+use sha1::Digest;
+use zip::{write::FileOptions, ZipWriter};
+
+use super::import;
+use crate::{layer::GameInfo, lock::LockedVariant};
+
+/// Files of the game directory which the game or mod loaders regenerate, left out in addition to the ones
+/// which are not imported
+const EXCLUDED: [&str; 4] = [".cache", ".fabric", ".mixin.out", "debug"];
+
+/// The archive formats a variant can be exported as
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum ExportFormat {
+    /// A Modrinth modpack, mods installed from Modrinth are referenced instead of included
+    Mrpack,
+    /// A MultiMC or Prism instance
+    Prism,
+    /// The contents of the game directory
+    Zip,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mrpack => "mrpack",
+            Self::Prism | Self::Zip => "zip",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Index<'a> {
+    format_version: u32,
+    game: &'static str,
+    version_id: &'a str,
+    name: &'a str,
+    files: Vec<IndexFile>,
+    dependencies: BTreeMap<&'static str, &'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexFile {
+    path: String,
+    hashes: BTreeMap<&'static str, String>,
+    downloads: Vec<String>,
+    file_size: u64,
+}
+
+/// Packages the game directory of a variant which was set up for `game` into the archive `target`,
+/// leaving out logs, caches and account data. `version` is the version of the modpack
+pub fn write(
+    format: ExportFormat,
+    name: &str,
+    version: &str,
+    game_dir: &Path,
+    game: &GameInfo,
+    lock: &LockedVariant,
+    target: &Path,
+) -> Result<()> {
+    let game_version = game.version.as_str();
+    let loader = game.loader;
+    let loader_version = game.loader_version.as_deref();
+    // Checked before creating the archive, so that no pack is left behind which can't be installed
+    let loader_dependency = match format {
+        ExportFormat::Mrpack => mrpack_loader(loader, loader_version)?,
+        _ => None,
+    };
+    let mut files = vec![];
+    collect_files(game_dir, "", &mut files)
+        .context(format!("Unable to read {}", game_dir.display()))?;
+    files.sort();
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut zip = ZipWriter::new(
+        fs::File::create(target).context(format!("Unable to create {}", target.display()))?,
+    );
+    match format {
+        ExportFormat::Zip => add_files(&mut zip, "", &files)?,
+        ExportFormat::Prism => {
+            zip.start_file(format!("{name}/instance.cfg"), FileOptions::default())?;
+            write!(zip, "InstanceType=OneSix\nname={name}\n")?;
+            zip.start_file(format!("{name}/mmc-pack.json"), FileOptions::default())?;
+            serde_json::to_writer_pretty(
+                &mut zip,
+                &mmc_pack(game_version, loader, loader_version),
+            )?;
+            add_files(&mut zip, &format!("{name}/.minecraft/"), &files)?;
+        }
+        ExportFormat::Mrpack => {
+            let index_files = modrinth_files(game_dir, lock)?;
+            files.retain(|(path, _)| !index_files.iter().any(|it| it.path == *path));
+            let mut dependencies = BTreeMap::from([("minecraft", game_version)]);
+            if let Some((loader_id, loader_version)) = loader_dependency {
+                dependencies.insert(loader_id, loader_version);
+            }
+            zip.start_file("modrinth.index.json", FileOptions::default())?;
+            serde_json::to_writer_pretty(
+                &mut zip,
+                &Index {
+                    format_version: 1,
+                    game: "minecraft",
+                    version_id: version,
+                    name,
+                    files: index_files,
+                    dependencies,
+                },
+            )?;
+            add_files(&mut zip, "overrides/", &files)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// The id and version of the mod loader dependency of a Modrinth modpack, `None` for vanilla.
+/// Fails if the loader version is unknown, as the pack would be installed without its loader otherwise
+fn mrpack_loader(
+    loader: instance::Modloader,
+    loader_version: Option<&str>,
+) -> Result<Option<(&'static str, &str)>> {
+    let id = match loader {
+        instance::Modloader::Fabric => "fabric-loader",
+        instance::Modloader::Quilt => "quilt-loader",
+        instance::Modloader::Forge => "forge",
+        _ => return Ok(None),
+    };
+    let version = loader_version.context(format!(
+        "The {loader} version of the variant is unknown, Modrinth modpacks require it"
+    ))?;
+    Ok(Some((id, version)))
+}
+
+/// Collects the files below `directory` with their path relative to the game directory, separated by `/`
+fn collect_files(directory: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if prefix.is_empty()
+            && (import::EXCLUDED.contains(&name.as_str()) || EXCLUDED.contains(&name.as_str()))
+        {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{path}/"), files)?;
+        } else {
+            files.push((path, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn add_files(
+    zip: &mut ZipWriter<fs::File>,
+    prefix: &str,
+    files: &[(String, PathBuf)],
+) -> Result<()> {
+    for (path, source) in files {
+        zip.start_file(format!("{prefix}{path}"), FileOptions::default())?;
+        io::copy(
+            &mut fs::File::open(source).context(format!("Unable to read {}", source.display()))?,
+            zip,
+        )?;
+    }
+    Ok(())
+}
+
+/// The components of a MultiMC or Prism instance, fabric and quilt need the intermediary mappings
+fn mmc_pack(
+    game_version: &str,
+    loader: instance::Modloader,
+    loader_version: Option<&str>,
+) -> serde_json::Value {
+    let mut components = vec![json!({ "uid": "net.minecraft", "version": game_version })];
+    let uid = match loader {
+        instance::Modloader::Fabric => Some("net.fabricmc.fabric-loader"),
+        instance::Modloader::Quilt => Some("org.quiltmc.quilt-loader"),
+        instance::Modloader::Forge => Some("net.minecraftforge"),
+        _ => None,
+    };
+    if matches!(
+        loader,
+        instance::Modloader::Fabric | instance::Modloader::Quilt
+    ) {
+        components.push(json!({ "uid": "net.fabricmc.intermediary", "version": game_version }));
+    }
+    if let (Some(uid), Some(version)) = (uid, loader_version) {
+        components.push(json!({ "uid": uid, "version": version }));
+    }
+    json!({ "formatVersion": 1, "components": components })
+}
+
+/// Index entries for the files installed from Modrinth, which the launcher downloads instead of them being included.
+/// Files which later layers removed or replaced are skipped
+fn modrinth_files(game_dir: &Path, lock: &LockedVariant) -> Result<Vec<IndexFile>> {
+    let mut files: Vec<IndexFile> = vec![];
+    for file in lock.mods.values().flatten() {
+        let path = format!("mods/{}", file.filename);
+        if files.iter().any(|it| it.path == path) {
+            continue;
+        }
+        let Ok(data) = fs::read(game_dir.join(&path)) else {
+            continue;
+        };
+        let sha1 = hex::encode(sha1::Sha1::digest(&data));
+        if !sha1.eq_ignore_ascii_case(&file.sha1) {
+            continue;
+        }
+        files.push(IndexFile {
+            path,
+            hashes: BTreeMap::from([
+                ("sha1", sha1),
+                ("sha512", hex::encode(sha2::Sha512::digest(&data))),
+            ]),
+            downloads: vec![file.url.clone()],
+            file_size: data.len().try_into()?,
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, io::Read, path::Path};
+
+    use helixlauncher_core::launch::instance;
+    use serde_json::json;
+    use sha1::Digest;
+
+    use super::{collect_files, mmc_pack, modrinth_files, write, ExportFormat};
+    use crate::{
+        layer::{test_util::temp_dir, GameInfo},
+        lock::{LockedFile, LockedVariant},
+    };
+
+    fn locked_file(filename: &str, content: &str) -> LockedFile {
+        LockedFile {
+            project_id: filename.to_owned(),
+            version_id: filename.to_owned(),
+            filename: filename.to_owned(),
+            url: format!("https://cdn.modrinth.com/{filename}"),
+            sha1: hex::encode(sha1::Sha1::digest(content)),
+        }
+    }
+
+    fn write_files(directory: &Path, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn lists_mmc_components() {
+        for (loader, loader_version, expected) in [
+            (
+                instance::Modloader::Vanilla,
+                None,
+                json!([{ "uid": "net.minecraft", "version": "1.20.1" }]),
+            ),
+            (
+                instance::Modloader::Fabric,
+                Some("0.14.21"),
+                json!([
+                    { "uid": "net.minecraft", "version": "1.20.1" },
+                    { "uid": "net.fabricmc.intermediary", "version": "1.20.1" },
+                    { "uid": "net.fabricmc.fabric-loader", "version": "0.14.21" },
+                ]),
+            ),
+            (
+                instance::Modloader::Quilt,
+                Some("0.19.2"),
+                json!([
+                    { "uid": "net.minecraft", "version": "1.20.1" },
+                    { "uid": "net.fabricmc.intermediary", "version": "1.20.1" },
+                    { "uid": "org.quiltmc.quilt-loader", "version": "0.19.2" },
+                ]),
+            ),
+            (
+                instance::Modloader::Forge,
+                Some("47.1.0"),
+                json!([
+                    { "uid": "net.minecraft", "version": "1.20.1" },
+                    { "uid": "net.minecraftforge", "version": "47.1.0" },
+                ]),
+            ),
+        ] {
+            assert_eq!(
+                mmc_pack("1.20.1", loader, loader_version),
+                json!({ "formatVersion": 1, "components": expected }),
+                "{loader}"
+            );
+        }
+    }
+
+    #[test]
+    fn references_unchanged_modrinth_files() {
+        let game_dir = temp_dir("export-modrinth-files");
+        write_files(
+            &game_dir,
+            &[("mods/a.jar", "a"), ("mods/b.jar", "replaced")],
+        );
+        let lock = LockedVariant {
+            mods: BTreeMap::from([
+                (
+                    "a".to_owned(),
+                    vec![locked_file("a.jar", "a"), locked_file("c.jar", "c")],
+                ),
+                ("b".to_owned(), vec![locked_file("b.jar", "b")]),
+                (
+                    "a (with dependencies)".to_owned(),
+                    vec![locked_file("a.jar", "a")],
+                ),
+            ]),
+            ..Default::default()
+        };
+        let files = modrinth_files(&game_dir, &lock).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "mods/a.jar");
+        assert_eq!(files[0].downloads, ["https://cdn.modrinth.com/a.jar"]);
+        assert_eq!(files[0].file_size, 1);
+        assert_eq!(files[0].hashes["sha1"], lock.mods["a"][0].sha1);
+        assert_eq!(files[0].hashes["sha512"].len(), 128);
+    }
+
+    #[test]
+    fn excludes_launcher_files() {
+        let game_dir = temp_dir("export-exclusion");
+        write_files(
+            &game_dir,
+            &[
+                ("options.txt", ""),
+                ("config/logs/kept.txt", ""),
+                ("logs/latest.log", ""),
+                (".fabric/remapped.jar", ""),
+                ("launcher_accounts.json", ""),
+            ],
+        );
+        let mut files = vec![];
+        collect_files(&game_dir, "", &mut files).unwrap();
+        let mut paths: Vec<String> = files.into_iter().map(|(path, _)| path).collect();
+        paths.sort();
+        assert_eq!(paths, ["config/logs/kept.txt", "options.txt"]);
+    }
+
+    #[test]
+    fn writes_mrpack() {
+        let directory = temp_dir("export-mrpack");
+        let game_dir = directory.join("game");
+        write_files(
+            &game_dir,
+            &[("mods/a.jar", "a"), ("options.txt", "fov:1.0")],
+        );
+        let lock = LockedVariant {
+            mods: BTreeMap::from([("a".to_owned(), vec![locked_file("a.jar", "a")])]),
+            ..Default::default()
+        };
+        let game = GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Fabric,
+            loader_version: Some("0.14.21".to_owned()),
+        };
+        let target = directory.join("pack.mrpack");
+        write(
+            ExportFormat::Mrpack,
+            "pack",
+            "1.2.0",
+            &game_dir,
+            &game,
+            &lock,
+            &target,
+        )
+        .unwrap();
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&target).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["modrinth.index.json", "overrides/options.txt"]);
+        let mut index = String::new();
+        archive
+            .by_name("modrinth.index.json")
+            .unwrap()
+            .read_to_string(&mut index)
+            .unwrap();
+        let index: serde_json::Value = serde_json::from_str(&index).unwrap();
+        assert_eq!(index["versionId"], "1.2.0");
+        assert_eq!(index["name"], "pack");
+        assert_eq!(
+            index["dependencies"],
+            json!({ "minecraft": "1.20.1", "fabric-loader": "0.14.21" })
+        );
+        assert_eq!(index["files"][0]["path"], "mods/a.jar");
+    }
+
+    #[test]
+    fn requires_loader_version_for_mrpack() {
+        let directory = temp_dir("export-missing-loader-version");
+        let game_dir = directory.join("game");
+        write_files(&game_dir, &[("options.txt", "")]);
+        let game = GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Quilt,
+            loader_version: None,
+        };
+        let target = directory.join("pack.mrpack");
+        let error = write(
+            ExportFormat::Mrpack,
+            "pack",
+            "1.0.0",
+            &game_dir,
+            &game,
+            &LockedVariant::default(),
+            &target,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("version of the variant is unknown"));
+        assert!(!target.exists());
+
+        let game = GameInfo {
+            loader: instance::Modloader::Vanilla,
+            ..game
+        };
+        write(
+            ExportFormat::Mrpack,
+            "pack",
+            "1.0.0",
+            &game_dir,
+            &game,
+            &LockedVariant::default(),
+            &target,
+        )
+        .unwrap();
+        assert!(target.is_file());
+    }
+}
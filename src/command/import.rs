@@ -0,0 +1,422 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use helixlauncher_core::launch::instance;
+use serde::Deserialize;
+
+use crate::layer::Layer;
+
+/// Files and directories of a game directory which are not copied, because they belong to the
+/// launcher, hold logs or contain account data
+pub(super) const EXCLUDED: [&str; 19] = [
+    "logs",
+    "crash-reports",
+    "assets",
+    "libraries",
+    "versions",
+    "natives",
+    "bin",
+    "runtime",
+    "webcache",
+    "webcache2",
+    "launcher_profiles.json",
+    "launcher_accounts.json",
+    "launcher_accounts_microsoft_store.json",
+    "launcher_msa_credentials.bin",
+    "launcher_settings.json",
+    "launcher_log.txt",
+    "launcher_ui_state.json",
+    "usercache.json",
+    "usernamecache.json",
+];
+
+/// An instance of another launcher
+pub struct Imported {
+    /// The name the instance has in its launcher
+    pub name: String,
+    pub instance: Layer,
+    /// The directory the game runs in, copied into the overlay
+    pub game_dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct MmcPack {
+    components: Vec<MmcComponent>,
+}
+
+#[derive(Deserialize)]
+struct MmcComponent {
+    uid: String,
+    version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LauncherProfiles {
+    profiles: HashMap<String, LauncherProfile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LauncherProfile {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    profile_type: Option<String>,
+    last_version_id: Option<String>,
+    game_dir: Option<PathBuf>,
+}
+
+/// Reads a MultiMC or Prism instance directory, or a vanilla launcher directory or its `launcher_profiles.json`.
+/// `select` picks one of the named vanilla launcher profiles if there are multiple
+pub fn read(path: &Path, select: impl FnOnce(&[String]) -> Result<usize>) -> Result<Imported> {
+    let directory = if path.is_file() {
+        path.parent().context("Invalid import path")?
+    } else {
+        path
+    };
+    if directory.join("mmc-pack.json").is_file() {
+        read_mmc(directory)
+    } else if directory.join("launcher_profiles.json").is_file() {
+        read_vanilla(directory, select)
+    } else {
+        bail!(
+            "{} is neither a MultiMC or Prism instance nor a minecraft launcher directory",
+            path.display()
+        )
+    }
+}
+
+fn read_mmc(directory: &Path) -> Result<Imported> {
+    let pack: MmcPack = serde_json::from_str(
+        &fs::read_to_string(directory.join("mmc-pack.json"))
+            .context("Unable to read mmc-pack.json")?,
+    )
+    .context("mmc-pack.json format invalid")?;
+    // instance.cfg is an ini file, only the name is of interest
+    let name = fs::read_to_string(directory.join("instance.cfg"))
+        .ok()
+        .and_then(|config| {
+            config
+                .lines()
+                .find_map(|it| it.strip_prefix("name="))
+                .map(|it| it.trim().to_owned())
+        })
+        .or_else(|| Some(directory.file_name()?.to_string_lossy().to_string()))
+        .context("Unable to determine the instance name")?;
+    let game_dir = [".minecraft", "minecraft"]
+        .iter()
+        .map(|it| directory.join(it))
+        .find(|it| it.is_dir())
+        .unwrap_or_else(|| directory.join(".minecraft"));
+    Ok(Imported {
+        name,
+        instance: mmc_instance(pack.components)?,
+        game_dir,
+    })
+}
+
+/// The instance layer for the components of a MultiMC or Prism instance
+fn mmc_instance(components: Vec<MmcComponent>) -> Result<Layer> {
+    let mut version = None;
+    let mut loader = instance::Modloader::Vanilla;
+    let mut loader_version = None;
+    for component in components {
+        match component.uid.as_str() {
+            "net.minecraft" => version = component.version,
+            "net.fabricmc.fabric-loader" => {
+                loader = instance::Modloader::Fabric;
+                loader_version = component.version;
+            }
+            "org.quiltmc.quilt-loader" => {
+                loader = instance::Modloader::Quilt;
+                loader_version = component.version;
+            }
+            "net.minecraftforge" => {
+                loader = instance::Modloader::Forge;
+                loader_version = component.version;
+            }
+            "net.neoforged" => bail!("NeoForge instances are not supported"),
+            _ => {}
+        }
+    }
+    Ok(Layer::Instance {
+        version: version.context("The instance has no minecraft component")?,
+        loader,
+        loader_version,
+    })
+}
+
+fn read_vanilla(
+    directory: &Path,
+    select: impl FnOnce(&[String]) -> Result<usize>,
+) -> Result<Imported> {
+    let profiles: LauncherProfiles = serde_json::from_str(
+        &fs::read_to_string(directory.join("launcher_profiles.json"))
+            .context("Unable to read launcher_profiles.json")?,
+    )
+    .context("launcher_profiles.json format invalid")?;
+    let mut profiles: Vec<(String, LauncherProfile)> = profiles
+        .profiles
+        .into_iter()
+        .map(|(id, profile)| {
+            let name = match profile.name.is_empty() {
+                true => id,
+                false => profile.name.clone(),
+            };
+            (name, profile)
+        })
+        .collect();
+    profiles.sort_by(|a, b| a.0.cmp(&b.0));
+    ensure!(!profiles.is_empty(), "The launcher has no profiles");
+    let index = match profiles.len() {
+        1 => 0,
+        _ => select(&profiles.iter().map(|it| it.0.clone()).collect::<Vec<_>>())?,
+    };
+    let (name, profile) = profiles.swap_remove(index);
+    let instance = match (profile.profile_type.as_deref(), profile.last_version_id) {
+        (Some(query @ ("latest-release" | "latest-snapshot")), _) => Layer::InstanceRange {
+            versions: query.to_owned(),
+            loader: instance::Modloader::Vanilla,
+            loader_version: None,
+            snapshots: query == "latest-snapshot",
+        },
+        (_, Some(version)) => {
+            let (version, loader, loader_version) = parse_version_id(&version);
+            Layer::Instance {
+                version,
+                loader,
+                loader_version,
+            }
+        }
+        _ => bail!("Launcher profile {name} has no version"),
+    };
+    Ok(Imported {
+        name,
+        instance,
+        game_dir: profile.game_dir.unwrap_or_else(|| directory.to_path_buf()),
+    })
+}
+
+/// Splits version ids of the vanilla launcher like `fabric-loader-0.14.21-1.20.1` or `1.20.1-forge-47.1.0`
+/// into game version, loader and loader version
+fn parse_version_id(id: &str) -> (String, instance::Modloader, Option<String>) {
+    for (prefix, loader) in [
+        ("fabric-loader-", instance::Modloader::Fabric),
+        ("quilt-loader-", instance::Modloader::Quilt),
+    ] {
+        if let Some((loader_version, version)) =
+            id.strip_prefix(prefix).and_then(|it| it.split_once('-'))
+        {
+            return (version.to_owned(), loader, Some(loader_version.to_owned()));
+        }
+    }
+    if let Some((version, loader_version)) = id.split_once("-forge") {
+        // Old forge versions repeat the game version, e.g. `1.12.2-forge1.12.2-14.23.5.2860`
+        let loader_version = loader_version.trim_start_matches('-');
+        let loader_version = loader_version
+            .strip_prefix(&format!("{version}-"))
+            .unwrap_or(loader_version);
+        return (
+            version.to_owned(),
+            instance::Modloader::Forge,
+            Some(loader_version.to_owned()),
+        );
+    }
+    (id.to_owned(), instance::Modloader::Vanilla, None)
+}
+
+/// Copies the game directory into `target`, leaving out launcher files, logs and account data
+pub fn copy_game_dir(source: &Path, target: &Path) -> Result<()> {
+    copy_all(source, target, &EXCLUDED, &mut vec![])
+        .context(format!("Unable to copy {}", source.display()))
+}
+
+/// Copies the contents of `source`, following symbolic links. `parents` holds the directories being copied,
+/// links to them are skipped as they would be copied endlessly
+fn copy_all(
+    source: &Path,
+    target: &Path,
+    excluded: &[&str],
+    parents: &mut Vec<PathBuf>,
+) -> Result<()> {
+    parents.push(source.canonicalize()?);
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if excluded.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let path = entry.path();
+        let destination = target.join(entry.file_name());
+        let Ok(metadata) = fs::metadata(&path) else {
+            eprintln!(
+                "Warning: {} is a broken link and was not copied",
+                path.display()
+            );
+            continue;
+        };
+        if !metadata.is_dir() {
+            fs::copy(&path, destination)?;
+        } else if parents.contains(&path.canonicalize()?) {
+            eprintln!(
+                "Warning: {} links to a directory containing it and was not copied",
+                path.display()
+            );
+        } else {
+            copy_all(&path, &destination, &[], parents)?;
+        }
+    }
+    parents.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use helixlauncher_core::launch::instance;
+
+    use super::{copy_game_dir, mmc_instance, parse_version_id, MmcComponent};
+    use crate::layer::{test_util::temp_dir, Layer};
+
+    #[test]
+    fn parses_version_ids() {
+        for (id, version, loader, loader_version) in [
+            ("1.20.1", "1.20.1", instance::Modloader::Vanilla, None),
+            ("23w31a", "23w31a", instance::Modloader::Vanilla, None),
+            (
+                "fabric-loader-0.14.21-1.20.1",
+                "1.20.1",
+                instance::Modloader::Fabric,
+                Some("0.14.21"),
+            ),
+            (
+                "quilt-loader-0.19.2-1.20.1",
+                "1.20.1",
+                instance::Modloader::Quilt,
+                Some("0.19.2"),
+            ),
+            (
+                "1.20.1-forge-47.1.0",
+                "1.20.1",
+                instance::Modloader::Forge,
+                Some("47.1.0"),
+            ),
+            (
+                "1.12.2-forge1.12.2-14.23.5.2860",
+                "1.12.2",
+                instance::Modloader::Forge,
+                Some("14.23.5.2860"),
+            ),
+        ] {
+            assert_eq!(
+                parse_version_id(id),
+                (
+                    version.to_owned(),
+                    loader,
+                    loader_version.map(str::to_owned)
+                ),
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn maps_mmc_components() {
+        let component = |uid: &str, version: &str| MmcComponent {
+            uid: uid.to_owned(),
+            version: Some(version.to_owned()),
+        };
+        for (components, expected) in [
+            (
+                vec![component("net.minecraft", "1.20.1")],
+                ("1.20.1", instance::Modloader::Vanilla, None),
+            ),
+            (
+                vec![
+                    component("org.lwjgl3", "3.3.1"),
+                    component("net.minecraft", "1.20.1"),
+                    component("net.fabricmc.intermediary", "1.20.1"),
+                    component("net.fabricmc.fabric-loader", "0.14.21"),
+                ],
+                ("1.20.1", instance::Modloader::Fabric, Some("0.14.21")),
+            ),
+            (
+                vec![
+                    component("net.minecraft", "1.20.1"),
+                    component("org.quiltmc.quilt-loader", "0.19.2"),
+                ],
+                ("1.20.1", instance::Modloader::Quilt, Some("0.19.2")),
+            ),
+            (
+                vec![
+                    component("net.minecraftforge", "47.1.0"),
+                    component("net.minecraft", "1.20.1"),
+                ],
+                ("1.20.1", instance::Modloader::Forge, Some("47.1.0")),
+            ),
+        ] {
+            let Layer::Instance {
+                version,
+                loader,
+                loader_version,
+            } = mmc_instance(components).unwrap()
+            else {
+                panic!("no instance layer");
+            };
+            assert_eq!(
+                (version.as_str(), loader, loader_version.as_deref()),
+                expected
+            );
+        }
+        assert!(mmc_instance(vec![component("net.fabricmc.fabric-loader", "0.14.21")]).is_err());
+        assert!(mmc_instance(vec![
+            component("net.minecraft", "1.20.1"),
+            component("net.neoforged", "20.2.86"),
+        ])
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_linked_directories() {
+        use std::os::unix::fs::symlink;
+
+        let directory = temp_dir("copy-game-dir");
+        let source = directory.join("source");
+        let shared = directory.join("shared-mods");
+        fs::create_dir_all(source.join("config")).unwrap();
+        fs::create_dir_all(source.join("logs")).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(source.join("options.txt"), "fov:1.0").unwrap();
+        fs::write(source.join("logs/latest.log"), "").unwrap();
+        fs::write(shared.join("sodium.jar"), "jar").unwrap();
+        symlink(&shared, source.join("mods")).unwrap();
+        symlink(
+            source.join("options.txt"),
+            source.join("config/options.txt"),
+        )
+        .unwrap();
+        symlink(&source, source.join("config/loop")).unwrap();
+        symlink(directory.join("missing"), source.join("broken")).unwrap();
+
+        let target = directory.join("target");
+        copy_game_dir(&source, &target).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("mods/sodium.jar")).unwrap(),
+            "jar"
+        );
+        assert!(!target.join("mods").is_symlink());
+        assert_eq!(
+            fs::read_to_string(target.join("config/options.txt")).unwrap(),
+            "fov:1.0"
+        );
+        assert!(!target.join("logs").exists());
+        assert!(!target.join("config/loop").exists());
+        assert!(!target.join("broken").exists());
+    }
+}
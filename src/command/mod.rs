@@ -1,2 +1,5 @@
 pub(crate) mod account;
+pub(crate) mod builder;
+pub(crate) mod export;
+pub(crate) mod import;
 pub(crate) mod profile;
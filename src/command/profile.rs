@@ -1,61 +1,126 @@
+use std::collections::{BTreeMap, HashSet};
 use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Duration;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
+use crate::command::{
+    builder,
+    export::{self, ExportFormat},
+    import,
+};
 use crate::layer;
-use crate::{config::ProfileConfig, layer::PreparedVariant};
+use crate::{
+    config::{ConfigFormat, ProfileConfig},
+    layer::PreparedVariant,
+};
 use anyhow::{bail, Context, Ok, Result, ensure};
 use futures::future::try_join_all;
 use helixlauncher_core::auth::account::AccountConfig;
-use helixlauncher_core::launch::instance;
 use indicatif::ProgressBar;
+use helixlauncher_core::launch::instance;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
 use crate::layer::Profile;
+use crate::lock::{LockFile, ProfileLock};
+use crate::report::{Report, VariantReport};
 
-pub async fn run(
+/// The given profile name, falling back to the active profile and prompting if neither is set.
+/// Returns `None` if the prompt was cancelled
+fn select_profile(
     name: Option<String>,
-    mut config: ProfileConfig,
-    account_config: AccountConfig,
-) -> Result<()> {
-    let profile_dir = config.path.parent().unwrap();
-    let name = match name {
-        Some(name) => name,
-        None => match config.active_config {
-            Some(name) => name,
+    config: &ProfileConfig,
+    interactive: bool,
+) -> Result<Option<String>> {
+    Ok(match name {
+        Some(name) => Some(name),
+        None => match &config.active_config {
+            Some(name) => Some(name.clone()),
             None => {
+                ensure!(
+                    interactive,
+                    "No profile is selected, pass the profile name or select one using `profile switch <name>`"
+                );
                 let options: Vec<String> = config.profiles.clone().into_keys().collect();
-                let index = match dialoguer::FuzzySelect::new()
+                dialoguer::FuzzySelect::new()
                     .with_prompt("Select profile")
                     .items(&options)
                     .interact_opt()
                     .context("Error while prompting profile name")?
-                {
-                    Some(index) => index,
-                    _ => return Ok(()),
-                };
-                options.get(index).unwrap().to_owned()
+                    .map(|index| options.get(index).unwrap().to_owned())
             }
         },
+    })
+}
+
+fn setup_context(config: &ProfileConfig, offline: bool) -> layer::SetupContext {
+    layer::SetupContext {
+        cache_directory: config.path.parent().unwrap().join(".cache"),
+        offline,
+    }
+}
+
+/// Parses `axis=value` filters and returns whether a variant matches all of them
+fn variant_filter(filters: &[String]) -> Result<impl Fn(&layer::Variant) -> bool> {
+    let filters: Vec<(String, String)> = filters
+        .iter()
+        .map(|it| {
+            it.split_once('=')
+                .map(|(axis, value)| (axis.to_owned(), value.to_owned()))
+                .context(format!("Filter `{it}` has to be in the form `axis=value`"))
+        })
+        .collect::<Result<_>>()?;
+    Ok(move |variant: &layer::Variant| {
+        filters
+            .iter()
+            .all(|(axis, value)| variant.axes().get(axis) == Some(value))
+    })
+}
+
+pub async fn run(
+    name: Option<String>,
+    filters: &[String],
+    config: ProfileConfig,
+    mut lock: LockFile,
+    account_config: AccountConfig,
+    offline: bool,
+    interactive: bool,
+) -> Result<()> {
+    let Some(name) = select_profile(name, &config, interactive)? else {
+        return Ok(());
     };
-    let profile = config
-        .profiles
-        .get_mut(&name)
-        .context("Profile does not exist")?;
-    let variants = profile.clone().get_variants(name.clone() + "_");
+    let context = setup_context(&config, offline);
+    let profile_dir = config.path.parent().unwrap();
+    let mut profile = config.resolve_profile(&name)?;
+    let profile_lock = lock.profiles.entry(name.clone()).or_default();
+    profile.expand(&context, profile_lock).await?;
+    let filter = variant_filter(filters)?;
+    let mut variants = profile.clone().get_variants(name.clone() + "_")?;
+    variants.retain(|it| filter(it));
     let setup_bar = Arc::new(ProgressBar::new(variants.len().try_into().unwrap()));
     profile.name = name.clone();
     let variants = variants.into_iter().map(|it: layer::Variant| {
         let path = profile_dir.join(&profile.name);
+        let context = context.clone();
+        let pinned = profile_lock.variants.get(it.name()).cloned();
         let setup_bar = setup_bar.clone();
         async move {
-            let result: PreparedVariant = it.setup(path).await?;
+            let result: PreparedVariant = it.setup(path, context, pinned).await?;
             setup_bar.inc(1);
             Ok::<PreparedVariant>(result)
         }
     });
     let variants = futures::future::try_join_all(variants).await?;
     setup_bar.finish();
+    profile_lock.variants.extend(
+        variants
+            .iter()
+            .map(|it| (it.report.name.clone(), it.lock.clone())),
+    );
+    lock.safe()?;
 
     let prepare_bar = Arc::new(ProgressBar::new(variants.len().try_into().unwrap()));
     prepare_bar.enable_steady_tick(Duration::from_secs(1));
@@ -64,9 +129,10 @@ pub async fn run(
         let prepare_bar = prepare_bar.clone();
         let account_config = account_config.clone();
         async move {
+            let report = it.report.clone();
             let result = it.run(account_config).await?;
             prepare_bar.inc(1);
-            Ok(result)
+            Ok((report, result))
         }
     });
     let variants = futures::future::try_join_all(variants).await?;
@@ -74,7 +140,11 @@ pub async fn run(
 
     let launch_bar = Arc::new(ProgressBar::new(variants.len().try_into().unwrap()));
     launch_bar.enable_steady_tick(Duration::from_secs(1));
-    for variant in variants {
+    let mut report = Report {
+        profile: name.clone(),
+        variants: vec![],
+    };
+    for (variant_report, variant) in variants {
         let mut variant = variant;
         variant.stderr = Stdio::piped();
         variant.stdout = Stdio::piped();
@@ -107,80 +177,240 @@ pub async fn run(
                 Ok(())
             }
         });
-        try_join_all(vec![run, stderr, stdout]).await?;
+        let results = try_join_all(vec![run, stderr, stdout]).await?;
+        report.variants.push(VariantReport {
+            success: Some(results[0].is_ok()),
+            ..variant_report
+        });
+        report.save(&profile_dir.join(&name))?;
         launch_bar.inc(1);
     }
     launch_bar.finish();
     return Ok(());
 }
 
-pub async fn create(name: Option<String>, config: &mut ProfileConfig) -> Result<()> {
+/// Prints the variants the profile expands to, with all version queries resolved
+pub async fn plan(
+    name: Option<String>,
+    filters: &[String],
+    config: ProfileConfig,
+    mut lock: LockFile,
+    offline: bool,
+    interactive: bool,
+) -> Result<()> {
+    let Some(name) = select_profile(name, &config, interactive)? else {
+        return Ok(());
+    };
+    let mut profile = config.resolve_profile(&name)?;
+    let profile_lock = lock.profiles.entry(name.clone()).or_default();
+    profile
+        .expand(&setup_context(&config, offline), profile_lock)
+        .await?;
+    let filter = variant_filter(filters)?;
+    for variant in profile.get_variants(name.clone() + "_")? {
+        if !filter(&variant) {
+            continue;
+        }
+        println!("{variant}");
+    }
+    Ok(())
+}
+
+/// Drops the locked versions of the given profile, or of all profiles if none is given, and resolves them again.
+/// Mod versions are resolved again on the next run
+pub async fn update(
+    name: Option<String>,
+    config: ProfileConfig,
+    mut lock: LockFile,
+    offline: bool,
+) -> Result<()> {
+    let names: Vec<String> = match name {
+        Some(name) => {
+            ensure!(config.profiles.contains_key(&name), "Profile does not exist");
+            vec![name]
+        }
+        None => config.profiles.keys().cloned().collect(),
+    };
+    let context = setup_context(&config, offline);
+    for name in names {
+        let mut profile_lock = ProfileLock::default();
+        config
+            .resolve_profile(&name)?
+            .expand(&context, &mut profile_lock)
+            .await
+            .context(format!("Unable to update profile {name}"))?;
+        lock.profiles.insert(name.clone(), profile_lock);
+        println!("Updated locked versions of {name}");
+    }
+    lock.safe()
+}
+
+/// Prints the problems of the given profile, or of all profiles if none is given, failing if there are any.
+/// An error reading the config is reported as its only problem, including the location of invalid values
+pub async fn validate(name: Option<String>, config: Result<ProfileConfig>) -> Result<()> {
+    let config = match config {
+        Result::Ok(config) => config,
+        Err(e) => {
+            println!("{e:#}");
+            bail!("Found 1 problem");
+        }
+    };
+    let names: Vec<String> = match name {
+        Some(name) => {
+            ensure!(config.profiles.contains_key(&name), "Profile does not exist");
+            vec![name]
+        }
+        None => {
+            let mut names: Vec<String> = config.profiles.keys().cloned().collect();
+            names.sort();
+            names
+        }
+    };
+    let profile_dir = config.path.parent().unwrap();
+    // Profiles other profiles extend are usually partial, like a base profile without an instance
+    let extended: HashSet<&String> = config
+        .profiles
+        .values()
+        .flat_map(|it| &it.extends)
+        .collect();
+    let mut count = 0;
+    for name in names {
+        let problems = match config.resolve_profile(&name) {
+            Result::Ok(profile) => {
+                profile.problems(&profile_dir.join(&name), !extended.contains(&name))
+            }
+            Err(e) => vec![format!("{e:#}")],
+        };
+        if problems.is_empty() {
+            println!("{name}: valid");
+        }
+        for problem in &problems {
+            println!("{name}: {problem}");
+        }
+        count += problems.len();
+    }
+    ensure!(count == 0, "Found {count} problems");
+    Ok(())
+}
+
+/// Writes the profile config in `format` and removes the previous file
+pub async fn convert(format: ConfigFormat, mut config: ProfileConfig) -> Result<()> {
+    let target = config.path.with_extension(format.extension());
+    ensure!(
+        ConfigFormat::of(&config.path)? != format,
+        "Profile config is already in this format"
+    );
+    ensure!(!target.exists(), "{} already exists", target.display());
+    let source = std::mem::replace(&mut config.path, target);
+    config.safe()?;
+    std::fs::remove_file(&source).context(format!("Unable to remove {}", source.display()))?;
+    println!("Converted {} to {}", source.display(), config.path.display());
+    Ok(())
+}
+
+/// Prints the variants `profile` expands to, so the layers can be checked before saving
+async fn preview(profile: &Profile, context: &layer::SetupContext) -> Result<()> {
+    let mut preview = profile.clone();
+    preview.expand(context, &mut ProfileLock::default()).await?;
+    let variants = preview.get_variants(profile.name.clone() + "_")?;
+    println!("The profile has {} variants:", variants.len());
+    for variant in variants {
+        println!("{variant}");
+    }
+    Ok(())
+}
+
+/// The flags of `profile create`
+pub struct CreateOptions {
+    pub version: Option<String>,
+    pub loader: Option<instance::Modloader>,
+    pub loader_version: Option<String>,
+    pub offline: bool,
+    pub interactive: bool,
+}
+
+/// The instance layer of the `create` flags, `None` if the layers should be prompted for instead.
+/// Fails if there is no version in non-interactive mode
+fn instance_layer(options: &CreateOptions) -> Result<Option<layer::Layer>> {
+    let Some(version) = options.version.clone() else {
+        ensure!(
+            options.interactive,
+            "Missing the minecraft version of the profile, pass it using --version"
+        );
+        return Ok(None);
+    };
+    let loader = options.loader.unwrap_or(instance::Modloader::Vanilla);
+    // Mod loaders need a version, which is resolved to the newest stable one when running the profile
+    let loader_version = match loader {
+        instance::Modloader::Vanilla => options.loader_version.clone(),
+        _ => Some(
+            options
+                .loader_version
+                .clone()
+                .unwrap_or_else(|| "latest-stable".to_owned()),
+        ),
+    };
+    Ok(Some(layer::Layer::Instance {
+        version,
+        loader,
+        loader_version,
+    }))
+}
+
+/// Creates a profile, with an instance layer if a version is given and otherwise prompting for layers
+pub async fn create(
+    name: Option<String>,
+    config: &mut ProfileConfig,
+    options: CreateOptions,
+) -> Result<()> {
     let name = match name {
         Some(name) => name,
-        None => dialoguer::Input::new()
-            .with_prompt("Enter profile name")
-            .interact_text()
-            .context("Error while rompting profile name")?,
+        None => {
+            ensure!(
+                options.interactive,
+                "Missing the profile name, pass it as argument"
+            );
+            dialoguer::Input::new()
+                .with_prompt("Enter profile name")
+                .interact_text()
+                .context("Error while rompting profile name")?
+        }
     };
     if config.profiles.contains_key(&name) {
         bail!("Profile already exists");
     }
     let mut new_profile = Profile {
         layers: vec![],
+        extends: vec![],
+        vars: BTreeMap::new(),
         name: name.clone(),
     };
-    if match dialoguer::Confirm::new()
-        .with_prompt("Generate minecraft and mod loader layers?")
+    if let Some(layer) = instance_layer(&options)? {
+        new_profile.layers.push(layer);
+    } else if match dialoguer::Confirm::new()
+        .with_prompt("Add layers now?")
         .interact_opt()
     {
         core::result::Result::Ok(Some(value)) => value,
         core::result::Result::Ok(None) => return Ok(()),
         Err(err) => {
-            eprintln!("Unable to prompt wether to generate default layers: {err}, assuming no");
+            eprintln!("Unable to prompt wether to add layers: {err}, assuming no");
             false
         }
     } {
-        let mut instance = layer::Layer::Instance {
-            version: dialoguer::Input::new()
-                .with_prompt("Minecraft Version")
-                .interact_text()
-                .context("Unable to prompt minecraft version")?,
-            loader: instance::Modloader::Vanilla,
-            loader_version: None,
-        };
-        let modloaders = [
-            instance::Modloader::Vanilla,
-            instance::Modloader::Quilt,
-            instance::Modloader::Fabric,
-            instance::Modloader::Forge,
-        ];
-        match dialoguer::FuzzySelect::new()
-            .items(&modloaders)
-            .interact_opt()
-            .context("Unable to prompt mod loader")?
+        let context = setup_context(config, options.offline);
+        new_profile.layers = builder::prompt_layers(&context).await?;
+        if let Err(e) = preview(&new_profile, &context).await {
+            eprintln!("Unable to preview the variants: {e:#}");
+        }
+        if !dialoguer::Confirm::new()
+            .with_prompt("Save profile?")
+            .default(true)
+            .interact()
+            .context("Unable to prompt saving")?
         {
-            Some(index) => match modloaders[index] {
-                instance::Modloader::Vanilla => {}
-                loader => {
-                    if let layer::Layer::Instance { version, .. } = instance {
-                        instance = layer::Layer::Instance {
-                            version,
-                            loader,
-                            loader_version: Some(
-                                dialoguer::Input::new()
-                                    .with_prompt(format!("Select {loader} version"))
-                                    .interact_text()
-                                    .context("Unable to prompt loader version")?,
-                            ),
-                        };
-                    } else {
-                        panic!("Static, should never happen!")
-                    }
-                }
-            },
-            None => return Ok(()),
+            return Ok(());
         }
-        new_profile.layers.push(instance);
     }
     config.profiles.insert(name.clone(), new_profile);
     config.safe()?;
@@ -188,17 +418,22 @@ pub async fn create(name: Option<String>, config: &mut ProfileConfig) -> Result<
     Ok(())
 }
 
-pub async fn switch(name: Option<String>, config: &mut ProfileConfig) -> Result<()> {
+pub async fn switch(
+    name: Option<String>,
+    config: &mut ProfileConfig,
+    interactive: bool,
+) -> Result<()> {
     match name {
         Some(string) => {
             if config.profiles.contains_key(&string) {
                 config.active_config = Some(string);
-                config.safe()?;
+                config.safe_state()?;
             } else {
                 bail!("This profile does not exist");
             }
         }
         None => {
+            ensure!(interactive, "Missing the profile name, pass it as argument");
             let options: Vec<String> = config.profiles.clone().into_keys().collect();
             let dialog = dialoguer::FuzzySelect::new()
                 .with_prompt("Select profile")
@@ -210,11 +445,11 @@ pub async fn switch(name: Option<String>, config: &mut ProfileConfig) -> Result<
             match dialog {
                 Some(0) => {
                     config.active_config = None;
-                    config.safe()?;
+                    config.safe_state()?;
                 }
                 Some(index) => {
                     config.active_config = Some(options.get(index - 1).unwrap().to_owned());
-                    config.safe()?;
+                    config.safe_state()?;
                 }
                 _ => {}
             }
@@ -222,3 +457,495 @@ pub async fn switch(name: Option<String>, config: &mut ProfileConfig) -> Result<
     }
     Ok(())
 }
+
+/// The number of variants of a profile, using only the locked and cached versions
+async fn variant_count(
+    name: &str,
+    config: &ProfileConfig,
+    lock: &LockFile,
+    context: &layer::SetupContext,
+) -> Result<usize> {
+    let mut profile = config.resolve_profile(name)?;
+    let mut profile_lock = lock.profiles.get(name).cloned().unwrap_or_default();
+    profile.expand(context, &mut profile_lock).await?;
+    Ok(profile.get_variants(name.to_owned() + "_")?.len())
+}
+
+/// Lists all profiles with their number of variants, marking the selected one
+pub async fn list(config: ProfileConfig, lock: LockFile) -> Result<()> {
+    let context = setup_context(&config, true);
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+    for name in names {
+        let marker = if config.active_config.as_ref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        // Profiles which can't be expanded without network access have an unknown count
+        let count = variant_count(name, &config, &lock, &context)
+            .await
+            .map_or("?".to_owned(), |it| it.to_string());
+        println!("{marker} {name} ({count} variants)");
+    }
+    Ok(())
+}
+
+/// Prints a profile in the format of the config, with inherited layers and fragments if `resolved` is set
+pub async fn show(name: String, resolved: bool, config: ProfileConfig) -> Result<()> {
+    let profile = match resolved {
+        true => config.resolve_profile(&name)?,
+        false => config
+            .profiles
+            .get(&name)
+            .context("Profile does not exist")?
+            .clone(),
+    };
+    let content = ConfigFormat::of(&config.path)?.serialize(&profile)?;
+    println!("{}", content.trim_end());
+    Ok(())
+}
+
+pub async fn delete(name: String, mut config: ProfileConfig, mut lock: LockFile) -> Result<()> {
+    ensure!(config.profiles.contains_key(&name), "Profile does not exist");
+    if let Some((child, _)) = config
+        .profiles
+        .iter()
+        .find(|(_, it)| it.extends.contains(&name))
+    {
+        bail!("Profile {child} extends {name}, so it can't be deleted");
+    }
+    config.profiles.remove(&name);
+    if config.active_config.as_ref() == Some(&name) {
+        config.active_config = None;
+    }
+    config.safe()?;
+    lock.profiles.remove(&name);
+    lock.safe()?;
+    let variant_dir = config.path.parent().unwrap().join(&name);
+    if variant_dir.exists() {
+        println!(
+            "Profile {name} was deleted, its variants are kept in {}",
+            variant_dir.display()
+        );
+    } else {
+        println!("Profile {name} was deleted");
+    }
+    Ok(())
+}
+
+/// Renames a profile together with its variant directories, locked versions and references to it
+pub async fn rename(
+    name: String,
+    new_name: String,
+    mut config: ProfileConfig,
+    mut lock: LockFile,
+) -> Result<()> {
+    ensure!(config.profiles.contains_key(&name), "Profile does not exist");
+    ensure!(
+        !config.profiles.contains_key(&new_name),
+        "Profile already exists"
+    );
+    // Variant names start with the profile name, so they change as well
+    let old_prefix = format!("{name}_");
+    let new_prefix = format!("{new_name}_");
+    let rename_variant = |variant: &str| match variant.strip_prefix(&old_prefix) {
+        Some(rest) => format!("{new_prefix}{rest}"),
+        None => variant.to_owned(),
+    };
+
+    let profile_dir = config.path.parent().unwrap().to_path_buf();
+    let source = profile_dir.join(&name);
+    if source.is_dir() {
+        let target = profile_dir.join(&new_name);
+        ensure!(!target.exists(), "{} already exists", target.display());
+        fs::rename(&source, &target).context("Unable to move variant directory")?;
+        for entry in fs::read_dir(&target)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if file_name.starts_with(&old_prefix) {
+                fs::rename(entry.path(), target.join(rename_variant(&file_name)))
+                    .context(format!("Unable to move variant {file_name}"))?;
+            }
+        }
+    }
+    if let Some(source) = config.sources.remove(&name) {
+        let extension = source.extension().unwrap().to_string_lossy().to_string();
+        let target = source.with_file_name(format!("{new_name}.{extension}"));
+        fs::rename(&source, &target).context(format!("Unable to move {}", source.display()))?;
+        config.sources.insert(new_name.clone(), target);
+    }
+
+    let profile = config.profiles.remove(&name).unwrap();
+    config.profiles.insert(new_name.clone(), profile);
+    for profile in config.profiles.values_mut() {
+        for parent in &mut profile.extends {
+            if *parent == name {
+                *parent = new_name.clone();
+            }
+        }
+    }
+    if config.active_config.as_ref() == Some(&name) {
+        config.active_config = Some(new_name.clone());
+    }
+    config.safe()?;
+    if let Some(mut profile_lock) = lock.profiles.remove(&name) {
+        profile_lock.variants = profile_lock
+            .variants
+            .into_iter()
+            .map(|(variant, locked)| (rename_variant(&variant), locked))
+            .collect();
+        lock.profiles.insert(new_name.clone(), profile_lock);
+    }
+    lock.safe()?;
+    println!("Profile {name} was renamed to {new_name}");
+    Ok(())
+}
+
+pub async fn copy(name: String, new_name: String, mut config: ProfileConfig) -> Result<()> {
+    let profile = config
+        .profiles
+        .get(&name)
+        .context("Profile does not exist")?
+        .clone();
+    ensure!(
+        !config.profiles.contains_key(&new_name),
+        "Profile already exists"
+    );
+    config.profiles.insert(new_name.clone(), profile);
+    config.safe()?;
+    println!("Profile {name} was copied to {new_name}");
+    Ok(())
+}
+
+/// Opens a profile in `$VISUAL` or `$EDITOR` and saves it if it can still be parsed and resolved afterwards.
+/// If the edited profile has other problems, it is only saved after confirming them
+pub async fn edit(name: String, mut config: ProfileConfig, interactive: bool) -> Result<()> {
+    ensure!(
+        interactive,
+        "Editing a profile requires an interactive terminal, use `profile show` and the config files instead"
+    );
+    let profile = config
+        .profiles
+        .get(&name)
+        .context("Profile does not exist")?;
+    let format = ConfigFormat::of(config.sources.get(&name).unwrap_or(&config.path))?;
+    let file = env::temp_dir().join(format!("{name}.{}", format.extension()));
+    fs::write(&file, format.serialize(profile)?)
+        .context(format!("Unable to write {}", file.display()))?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(target_os = "windows") {
+                "notepad".to_owned()
+            } else {
+                "vi".to_owned()
+            }
+        });
+    let mut command = editor.split_whitespace();
+    let status = std::process::Command::new(command.next().context("The editor is empty")?)
+        .args(command)
+        .arg(&file)
+        .status()
+        .context(format!("Unable to run {editor}"))?;
+    ensure!(
+        status.success(),
+        "{editor} exited unsuccessfully, the profile was not changed"
+    );
+
+    let rollback = |problems: Vec<String>| {
+        anyhow::anyhow!(
+            "The edited profile is invalid, the profile was not changed. The edit is kept in {}:\n{}",
+            file.display(),
+            problems.join("\n")
+        )
+    };
+    let edited = format
+        .parse(&fs::read_to_string(&file)?)
+        .map_err(|e| rollback(vec![format!("{e:#}")]))?;
+    config.profiles.insert(name.clone(), edited);
+    let profile = config
+        .resolve_profile(&name)
+        .map_err(|e| rollback(vec![format!("{e:#}")]))?;
+    let extended = config
+        .profiles
+        .values()
+        .any(|it| it.extends.contains(&name));
+    let problems = profile.problems(&config.path.parent().unwrap().join(&name), !extended);
+    // Some problems may be fixed later, like overlay sources which don't exist yet
+    if !problems.is_empty() {
+        eprintln!("The edited profile has problems:\n{}", problems.join("\n"));
+        if !dialoguer::Confirm::new()
+            .with_prompt("Save it anyway?")
+            .default(false)
+            .interact()
+            .context("Unable to prompt saving")?
+        {
+            bail!(
+                "The profile was not changed, the edit is kept in {}",
+                file.display()
+            );
+        }
+    }
+    config.safe()?;
+    fs::remove_file(&file).ok();
+    println!("Profile {name} was updated");
+    Ok(())
+}
+
+/// Creates a profile from an instance of MultiMC, Prism or the vanilla launcher. The game directory
+/// is copied to `imports/<name>` and applied as overlay
+pub async fn import(
+    path: &Path,
+    name: Option<String>,
+    entry: Option<String>,
+    config: &mut ProfileConfig,
+    interactive: bool,
+) -> Result<()> {
+    let imported = import::read(path, |names| match entry {
+        Some(entry) => names
+            .iter()
+            .position(|it| *it == entry)
+            .context(format!("The launcher has no profile named {entry}")),
+        None => {
+            ensure!(
+                interactive,
+                "The launcher has multiple profiles, pass one of {} using --entry",
+                names.join(", ")
+            );
+            dialoguer::FuzzySelect::new()
+                .with_prompt("Select launcher profile")
+                .items(names)
+                .interact_opt()
+                .context("Error while prompting launcher profile")?
+                .context("No launcher profile was selected")
+        }
+    })?;
+    let name = name.unwrap_or(imported.name);
+    ensure!(
+        !config.profiles.contains_key(&name),
+        "Profile {name} already exists, pass another name using --name"
+    );
+    let mut layers = vec![imported.instance];
+    if imported.game_dir.is_dir() {
+        let overlay = config.path.parent().unwrap().join("imports").join(&name);
+        ensure!(!overlay.exists(), "{} already exists", overlay.display());
+        import::copy_game_dir(&imported.game_dir, &overlay.join(".minecraft"))?;
+        // Overlay sources are relative to the variant directory
+        layers.push(layer::Layer::DirectoryOverlay {
+            source: PathBuf::from("../../imports").join(&name),
+        });
+    }
+    config.profiles.insert(
+        name.clone(),
+        Profile {
+            name: name.clone(),
+            extends: vec![],
+            vars: BTreeMap::new(),
+            layers,
+        },
+    );
+    config.safe()?;
+    println!("Profile {name} was imported from {}", path.display());
+    Ok(())
+}
+
+/// The flags of `profile export`
+pub struct ExportOptions {
+    pub variant: Option<String>,
+    pub pack_version: Option<String>,
+    pub format: ExportFormat,
+    pub output: Option<PathBuf>,
+    pub offline: bool,
+    pub interactive: bool,
+}
+
+/// Sets up a variant of the profile and packages it as a Modrinth modpack, a Prism instance or a zip of its game directory.
+/// The variant can be given by its full name or by the part after the profile name
+pub async fn export(
+    name: Option<String>,
+    config: ProfileConfig,
+    mut lock: LockFile,
+    options: ExportOptions,
+) -> Result<()> {
+    let ExportOptions {
+        variant,
+        pack_version,
+        format,
+        output,
+        offline,
+        interactive,
+    } = options;
+    let Some(name) = select_profile(name, &config, interactive)? else {
+        return Ok(());
+    };
+    let context = setup_context(&config, offline);
+    let profile_dir = config.path.parent().unwrap();
+    let mut profile = config.resolve_profile(&name)?;
+    let profile_lock = lock.profiles.entry(name.clone()).or_default();
+    profile.expand(&context, profile_lock).await?;
+    let prefix = name.clone() + "_";
+    let mut variants = profile.get_variants(prefix.clone())?;
+    ensure!(!variants.is_empty(), "Profile {name} has no variants");
+    let names: Vec<&str> = variants.iter().map(|it| it.name()).collect();
+    let index = match variant {
+        Some(variant) => names
+            .iter()
+            .position(|it| *it == variant || it.strip_prefix(&prefix) == Some(variant.as_str()))
+            .context(format!("Profile {name} has no variant {variant}"))?,
+        None if names.len() == 1 => 0,
+        None => {
+            ensure!(
+                interactive,
+                "Profile {name} has multiple variants, pass one of {} using --variant",
+                names.join(", ")
+            );
+            let Some(index) = dialoguer::FuzzySelect::new()
+                .with_prompt("Select variant")
+                .items(&names)
+                .interact_opt()
+                .context("Error while prompting variant")?
+            else {
+                return Ok(());
+            };
+            index
+        }
+    };
+    let variant = variants.swap_remove(index);
+    let pinned = profile_lock.variants.get(variant.name()).cloned();
+    let prepared = variant
+        .setup(profile_dir.join(&name), context, pinned)
+        .await?;
+    let variant_name = prepared.report.name.clone();
+    profile_lock
+        .variants
+        .insert(variant_name.clone(), prepared.lock.clone());
+    lock.safe()?;
+    let target = output.unwrap_or_else(|| {
+        profile_dir
+            .join("exports")
+            .join(format!("{variant_name}.{}", format.extension()))
+    });
+    let game = prepared
+        .game
+        .as_ref()
+        .context(format!("Variant {variant_name} has no minecraft instance"))?;
+    export::write(
+        format,
+        &variant_name,
+        pack_version.as_deref().unwrap_or(&variant_name),
+        &profile_dir
+            .join(&name)
+            .join(&variant_name)
+            .join(layer::GAME_DIR),
+        game,
+        &prepared.lock,
+        &target,
+    )
+    .context(format!("Unable to export {variant_name}"))?;
+    println!(
+        "Variant {variant_name} was exported to {}",
+        target.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use helixlauncher_core::launch::instance::Modloader;
+    use serde_json::json;
+
+    use super::{convert, instance_layer, CreateOptions};
+    use crate::{
+        config::{ConfigFormat, ProfileConfig},
+        layer::test_util::temp_dir,
+    };
+
+    #[tokio::test]
+    async fn converts_config_format() {
+        let directory = temp_dir("convert");
+        let json_path = directory.join("profiles.json");
+        fs::write(
+            &json_path,
+            json!({
+                "schema_version": 1,
+                "profiles": { "test": { "layers": [
+                    { "instance": { "version": "1.20.1", "loader": "fabric", "loader_version": null } },
+                    { "variants": [{ "execute_command": "a" }, { "execute_command": "b" }] },
+                ] } },
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let config = ProfileConfig::read_or_create(json_path.clone()).unwrap();
+        let expected = serde_json::to_value(&config.profiles["test"]).unwrap();
+        assert!(convert(ConfigFormat::Json, config).await.is_err());
+
+        let config = ProfileConfig::read_or_create(json_path.clone()).unwrap();
+        convert(ConfigFormat::Toml, config).await.unwrap();
+        let toml_path = directory.join("profiles.toml");
+        assert!(!json_path.exists());
+        let converted = ProfileConfig::read_or_create(toml_path.clone()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&converted.profiles["test"]).unwrap(),
+            expected
+        );
+
+        // An existing config of the target format is not overwritten
+        fs::write(directory.join("profiles.yaml"), "").unwrap();
+        assert!(convert(ConfigFormat::Yaml, converted).await.is_err());
+        assert!(toml_path.is_file());
+    }
+
+    fn options(
+        version: Option<&str>,
+        loader: Option<Modloader>,
+        interactive: bool,
+    ) -> CreateOptions {
+        CreateOptions {
+            version: version.map(str::to_owned),
+            loader,
+            loader_version: None,
+            offline: false,
+            interactive,
+        }
+    }
+
+    #[test]
+    fn creates_instance_layer_from_flags() {
+        let error = instance_layer(&options(None, None, false)).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Missing the minecraft version of the profile, pass it using --version"
+        );
+        assert!(instance_layer(&options(None, None, true))
+            .unwrap()
+            .is_none());
+
+        let layer = instance_layer(&options(Some("1.20.1"), None, false)).unwrap();
+        assert_eq!(
+            serde_json::to_value(layer).unwrap(),
+            json!({ "instance": { "version": "1.20.1", "loader": "vanilla", "loader_version": null } })
+        );
+        let layer =
+            instance_layer(&options(Some("1.20.1"), Some(Modloader::Fabric), false)).unwrap();
+        assert_eq!(
+            serde_json::to_value(layer).unwrap(),
+            json!({ "instance": {
+                "version": "1.20.1",
+                "loader": "fabric",
+                "loader_version": "latest-stable",
+            } })
+        );
+        let mut pinned = options(Some("1.20.1"), Some(Modloader::Quilt), false);
+        pinned.loader_version = Some("0.20.2".to_owned());
+        assert_eq!(
+            serde_json::to_value(instance_layer(&pinned).unwrap()).unwrap()["instance"]
+                ["loader_version"],
+            "0.20.2"
+        );
+    }
+}
@@ -1,56 +1,675 @@
-use std::{collections::HashMap, fs, io, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::ValueEnum;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+use crate::layer::{Layer, Profile};
+
+/// The file formats the profile config can be written in, detected by the file extension
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    const EXTENSIONS: [&str; 4] = ["json", "yaml", "yml", "toml"];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+        }
+    }
+
+    pub fn of(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|it| it.to_str()) {
+            Some("json") => Ok(Self::Json),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            Some("toml") => Ok(Self::Toml),
+            _ => bail!("{} is not a json, yaml or toml file", path.display()),
+        }
+    }
+
+    /// Parses `content`, reporting the location and the path of the offending value on errors
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T> {
+        fn located<T, E: Display>(result: Result<T, serde_path_to_error::Error<E>>) -> Result<T> {
+            result.map_err(|e| anyhow!("{} (at `{}`)", e.inner(), e.path()))
+        }
+        match self {
+            Self::Json => located(serde_path_to_error::deserialize(
+                &mut serde_json::Deserializer::from_str(content),
+            )),
+            Self::Yaml => located(serde_path_to_error::deserialize(
+                serde_yaml::Deserializer::from_str(content),
+            )),
+            Self::Toml => located(serde_path_to_error::deserialize(toml::Deserializer::new(
+                content,
+            ))),
+        }
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            Self::Json => serde_json::to_string_pretty(value)?,
+            Self::Yaml => serde_yaml::to_string(value)?,
+            // TOML supports neither null nor enum variants with fields, which json objects represent
+            Self::Toml => {
+                let mut value = serde_json::to_value(value)?;
+                remove_nulls(&mut value);
+                toml::to_string_pretty(&value)?
+            }
+        })
+    }
+}
+
+/// Removes all object entries which are null, as they can't be written to TOML
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, it| !it.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// The version of the profile config layout, configs of older versions are migrated when read
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upgrades a config document of the version of its index to the next version.
+/// The path of the config file is passed along for migrations moving data into other files
+const MIGRATIONS: [fn(&mut serde_json::Value, &Path) -> Result<()>; SCHEMA_VERSION as usize] =
+    [move_active_config];
+
+/// Version 1 moves the selected profile from the config into the state file
+fn move_active_config(document: &mut serde_json::Value, path: &Path) -> Result<()> {
+    let active_config = document
+        .as_object_mut()
+        .and_then(|it| it.remove("active_config"));
+    let state_path = path.with_file_name(STATE_FILE);
+    if let Some(serde_json::Value::String(active_config)) = active_config {
+        if !state_path.exists() {
+            let state = State {
+                active_config: Some(active_config),
+            };
+            fs::write(state_path, serde_json::to_string_pretty(&state).unwrap())
+                .context("Error saving state file")?;
+        }
+    }
+    Ok(())
+}
+
+/// The directory next to the profile config holding one profile per file, named by the file stem
+const PROFILE_DIRECTORY: &str = "profiles.d";
+/// Local state like the selected profile, kept out of the profile config so it can be version controlled
+const STATE_FILE: &str = "profiles.state.json";
+
+#[derive(Deserialize, JsonSchema)]
 pub struct ProfileConfig {
-    pub profiles: HashMap<String, crate::layer::Profile>,
+    /// The schema editors validate the config with
+    #[serde(rename = "$schema")]
+    pub schema: Option<String>,
+
+    /// The version of the config layout, configs without one are of version 0
+    #[serde(default)]
+    pub schema_version: u32,
+
+    /// The profiles of the config file and of the files in `profiles.d`
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Reusable lists of layers, inserted into profiles with an `include` layer
+    #[serde(default)]
+    pub fragments: HashMap<String, Vec<Layer>>,
+
+    /// The selected profile, stored in the state file
+    #[serde(skip)]
     pub active_config: Option<String>,
 
     #[serde(skip)]
     pub path: PathBuf,
+
+    /// The files in `profiles.d` profiles were read from
+    #[serde(skip)]
+    pub sources: HashMap<String, PathBuf>,
+}
+
+/// The content of the profile config file, without the profiles stored in separate files
+#[derive(Serialize)]
+struct ConfigFile<'a> {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    schema: &'a Option<String>,
+    schema_version: u32,
+    profiles: BTreeMap<&'a String, &'a Profile>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fragments: BTreeMap<&'a String, &'a Vec<Layer>>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    active_config: Option<String>,
 }
 
 impl ProfileConfig {
+    /// The `profiles.json`, `profiles.yaml` or `profiles.toml` inside `directory`, `profiles.json` if none exists yet
+    pub fn find(directory: &Path) -> Result<PathBuf> {
+        let existing: Vec<PathBuf> = ConfigFormat::EXTENSIONS
+            .iter()
+            .map(|it| directory.join(format!("profiles.{it}")))
+            .filter(|it| it.is_file())
+            .collect();
+        match existing.as_slice() {
+            [] => Ok(directory.join("profiles.json")),
+            [path] => Ok(path.clone()),
+            _ => bail!(
+                "Found multiple profile configs, only one of {} may exist",
+                existing
+                    .iter()
+                    .map(|it| it.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
     pub fn read_or_create(path: PathBuf) -> Result<Self> {
+        let format = ConfigFormat::of(&path)?;
         match fs::read_to_string(&path) {
             Ok(content) => {
-                let mut it =
-                    Self::from(content.as_str()).context("Profile config format invalid")?;
+                let content = Self::migrate(&path, format, content)?;
+                let mut it: Self = format
+                    .parse(&content)
+                    .context("Profile config format invalid")?;
                 it.path = path;
+                it.read_profile_directory()?;
+                it.read_state()?;
                 Ok(it)
             }
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                fs::write(
-                    &path,
-                    serde_json::to_string_pretty(&Self {
-                        profiles: HashMap::new(),
-                        active_config: None,
-                        path: path.clone(),
-                    })
-                    .unwrap(),
-                )
-                .context("Unable to create config file")?;
-                Ok(Self {
+                let mut it = Self {
+                    schema: None,
+                    schema_version: SCHEMA_VERSION,
                     profiles: HashMap::new(),
+                    fragments: HashMap::new(),
                     active_config: None,
                     path,
-                })
+                    sources: HashMap::new(),
+                };
+                it.read_profile_directory()?;
+                it.read_state()?;
+                it.safe().context("Unable to create config file")?;
+                Ok(it)
             }
             Err(e) => Err(e).context("Could not read or create config file"),
         }
     }
 
+    /// Migrates the config to the current schema version, keeping the previous file as a backup
+    fn migrate(path: &Path, format: ConfigFormat, content: String) -> Result<String> {
+        let mut document: serde_json::Value = format
+            .parse(&content)
+            .context("Profile config format invalid")?;
+        let version = document
+            .get("schema_version")
+            .and_then(|it| it.as_u64())
+            .unwrap_or(0) as u32;
+        ensure!(
+            version <= SCHEMA_VERSION,
+            "Profile config has schema version {version}, but only versions up to {SCHEMA_VERSION} are supported"
+        );
+        if version == SCHEMA_VERSION {
+            return Ok(content);
+        }
+        let backup = path.with_file_name(format!(
+            "{}.v{version}.bak",
+            path.file_name().unwrap().to_string_lossy()
+        ));
+        fs::copy(path, &backup).context("Unable to back up profile config")?;
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut document, path).context(format!(
+                "Unable to migrate profile config from version {version}"
+            ))?;
+        }
+        document["schema_version"] = SCHEMA_VERSION.into();
+        let content = format.serialize(&document)?;
+        fs::write(path, &content).context("Error saving migrated config")?;
+        eprintln!(
+            "Migrated profile config from schema version {version} to {SCHEMA_VERSION}, the previous config was saved to {}",
+            backup.display()
+        );
+        Ok(content)
+    }
+
+    /// The json schema of the config, identified by its schema version
+    pub fn json_schema() -> serde_json::Value {
+        let mut schema = serde_json::to_value(schemars::schema_for!(ProfileConfig)).unwrap();
+        schema["$id"] = format!(
+            "urn:{}:profile-config:v{SCHEMA_VERSION}",
+            env!("CARGO_PKG_NAME")
+        )
+        .into();
+        schema["properties"]["schema_version"]["const"] = SCHEMA_VERSION.into();
+        schema
+    }
+
+    fn read_profile_directory(&mut self) -> Result<()> {
+        let directory = self.path.with_file_name(PROFILE_DIRECTORY);
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(format!("Unable to read {}", directory.display())),
+        };
+        let mut paths = entries
+            .map(|it| it.map(|it| it.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+        for path in paths {
+            let Ok(format) = ConfigFormat::of(&path) else {
+                continue;
+            };
+            let name = path
+                .file_stem()
+                .context("Invalid profile file name")?
+                .to_string_lossy()
+                .to_string();
+            ensure!(
+                !self.profiles.contains_key(&name),
+                "Profile `{name}` of {} is already defined",
+                path.display()
+            );
+            let profile = format
+                .parse(&fs::read_to_string(&path)?)
+                .context(format!("Profile format of {} invalid", path.display()))?;
+            self.profiles.insert(name.clone(), profile);
+            self.sources.insert(name, path);
+        }
+        Ok(())
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.path.with_file_name(STATE_FILE)
+    }
+
+    /// Reads the selected profile from the state file
+    fn read_state(&mut self) -> Result<()> {
+        match fs::read_to_string(self.state_path()) {
+            Ok(content) => {
+                let state: State =
+                    serde_json::from_str(&content).context("State file format invalid")?;
+                self.active_config = state.active_config;
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Could not read state file"),
+        }
+    }
+
+    /// Saves the selected profile without touching the profile config
+    pub fn safe_state(&self) -> Result<()> {
+        let state = State {
+            active_config: self.active_config.clone(),
+        };
+        fs::write(
+            self.state_path(),
+            serde_json::to_string_pretty(&state).unwrap(),
+        )
+        .context("Error saving state file")
+    }
+
+    /// Saves the config file and all profiles read from `profiles.d` which were changed or removed
     pub fn safe(&self) -> Result<()> {
-        fs::write(&self.path, serde_json::to_string_pretty(self).unwrap())
-            .context("Error saving config, state might be broken")
+        let file = ConfigFile {
+            schema: &self.schema,
+            schema_version: self.schema_version,
+            profiles: self
+                .profiles
+                .iter()
+                .filter(|(name, _)| !self.sources.contains_key(*name))
+                .collect(),
+            fragments: self.fragments.iter().collect(),
+        };
+        let content = ConfigFormat::of(&self.path)?.serialize(&file)?;
+        fs::write(&self.path, content).context("Error saving config, state might be broken")?;
+        for (name, path) in &self.sources {
+            let Some(profile) = self.profiles.get(name) else {
+                fs::remove_file(path).context(format!("Unable to remove {}", path.display()))?;
+                continue;
+            };
+            let format = ConfigFormat::of(path)?;
+            let content = format.serialize(profile)?;
+            // Unchanged profiles are not rewritten, so their formatting and comments are kept
+            let unchanged = fs::read_to_string(path)
+                .ok()
+                .and_then(|it| format.parse::<Profile>(&it).ok())
+                .and_then(|it| format.serialize(&it).ok())
+                .is_some_and(|it| it == content);
+            if !unchanged {
+                fs::write(path, content).context(format!("Error saving {}", path.display()))?;
+            }
+        }
+        self.safe_state()
     }
 
-    fn from(s: &str) -> serde_json::Result<Self> {
-        serde_json::from_str(s)
+    /// The profile with the layers of the profiles it extends prepended and all fragments included.
+    /// Variables of the profile take precedence over the ones of its parents
+    pub fn resolve_profile(&self, name: &str) -> Result<Profile> {
+        let mut profile = self.inherited_profile(name)?;
+        profile.layers = self
+            .include_fragments(profile.layers, &mut vec![])
+            .context(format!("Unable to include fragments into profile `{name}`"))?;
+        profile.name = name.to_owned();
+        Ok(profile)
+    }
+
+    /// The profile with the layers and variables of its ancestors merged in. Each ancestor is applied
+    /// once, even if it is reached through several parents
+    fn inherited_profile(&self, name: &str) -> Result<Profile> {
+        let mut ancestors = vec![];
+        self.collect_ancestors(name, &mut vec![], &mut ancestors)?;
+        let mut inherited = Profile {
+            name: String::new(),
+            extends: vec![],
+            vars: BTreeMap::new(),
+            layers: vec![],
+        };
+        for ancestor in ancestors {
+            let profile = &self.profiles[&ancestor];
+            inherited.vars.extend(profile.vars.clone());
+            inherited.layers.extend(profile.layers.iter().cloned());
+        }
+        Ok(inherited)
+    }
+
+    /// Appends the profiles `name` extends and then `name` itself to `ancestors`, parents in the order
+    /// they are listed and skipping those already added. `chain` holds the profiles currently being
+    /// visited to detect cycles
+    fn collect_ancestors(
+        &self,
+        name: &str,
+        chain: &mut Vec<String>,
+        ancestors: &mut Vec<String>,
+    ) -> Result<()> {
+        if let Some(child) = chain.last() {
+            if chain.iter().any(|it| it == name) {
+                bail!(
+                    "Profile `{child}` extends `{name}`, which results in cyclic inheritance: {} -> {name}",
+                    chain.join(" -> ")
+                );
+            }
+        }
+        let Some(profile) = self.profiles.get(name) else {
+            match chain.last() {
+                Some(child) => bail!("Profile `{child}` extends `{name}`, which does not exist"),
+                None => bail!("Profile does not exist"),
+            }
+        };
+        chain.push(name.to_owned());
+        for parent in &profile.extends {
+            if !ancestors.contains(parent) {
+                self.collect_ancestors(parent, chain, ancestors)?;
+            }
+        }
+        chain.pop();
+        ancestors.push(name.to_owned());
+        Ok(())
+    }
+
+    /// Replaces `include` layers with the layers of their fragment. Inside of other layers only
+    /// fragments consisting of a single layer can be included
+    fn include_fragments(&self, layers: Vec<Layer>, chain: &mut Vec<String>) -> Result<Vec<Layer>> {
+        let mut included = vec![];
+        for layer in layers {
+            match layer {
+                Layer::Include { fragment } => included.extend(self.fragment(&fragment, chain)?),
+                layer => included.push(layer.map_nested(&mut |it| match it {
+                    Layer::Include { fragment } => {
+                        let mut layers = self.fragment(&fragment, chain)?;
+                        ensure!(
+                            layers.len() == 1,
+                            "Fragment `{fragment}` is nested inside another layer and has to consist of exactly one layer"
+                        );
+                        Ok(layers.remove(0))
+                    }
+                    it => Ok(it),
+                })?),
+            }
+        }
+        Ok(included)
+    }
+
+    fn fragment(&self, name: &str, chain: &mut Vec<String>) -> Result<Vec<Layer>> {
+        ensure!(
+            !chain.iter().any(|it| it == name),
+            "Fragment `{name}` includes itself: {} -> {name}",
+            chain.join(" -> ")
+        );
+        let layers = self
+            .fragments
+            .get(name)
+            .context(format!("Fragment `{name}` does not exist"))?
+            .clone();
+        chain.push(name.to_owned());
+        let layers = self.include_fragments(layers, chain)?;
+        chain.pop();
+        Ok(layers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::fs;
+
+    use super::{move_active_config, ConfigFormat, ProfileConfig, SCHEMA_VERSION, STATE_FILE};
+    use crate::layer::{test_util::temp_dir, Layer, Profile};
+
+    fn config(value: serde_json::Value) -> ProfileConfig {
+        ConfigFormat::Json.parse(&value.to_string()).unwrap()
+    }
+
+    fn commands(layers: &[Layer]) -> Vec<String> {
+        layers
+            .iter()
+            .map(|it| match it {
+                Layer::ExecuteCommand(command) => command.clone(),
+                Layer::Variants(variants) => format!("variants {}", commands(variants).join(" ")),
+                _ => panic!("unexpected layer"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn moves_active_config_to_state_file() {
+        let directory = temp_dir("move-active-config");
+        let path = directory.join("profiles.json");
+        let mut document = json!({ "active_config": "a", "profiles": {} });
+        move_active_config(&mut document, &path).unwrap();
+        assert_eq!(document, json!({ "profiles": {} }));
+        let state = fs::read_to_string(directory.join(STATE_FILE)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&state).unwrap(),
+            json!({ "active_config": "a" })
+        );
+
+        // An existing state file is newer than the config, so it is kept
+        let mut document = json!({ "active_config": "b", "profiles": {} });
+        move_active_config(&mut document, &path).unwrap();
+        assert_eq!(document, json!({ "profiles": {} }));
+        assert_eq!(
+            fs::read_to_string(directory.join(STATE_FILE)).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn migrates_only_outdated_configs() {
+        let directory = temp_dir("migrate");
+        let path = directory.join("profiles.yaml");
+        let outdated = "active_config: a\nprofiles:\n  a:\n    layers: []\n";
+        fs::write(&path, outdated).unwrap();
+        let config = ProfileConfig::read_or_create(path.clone()).unwrap();
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert_eq!(config.active_config.as_deref(), Some("a"));
+        assert!(config.profiles.contains_key("a"));
+        assert_eq!(
+            fs::read_to_string(directory.join("profiles.yaml.v0.bak")).unwrap(),
+            outdated
+        );
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(migrated.contains("schema_version: 1"), "{migrated}");
+        assert!(!migrated.contains("active_config"), "{migrated}");
+
+        // Current configs are neither rewritten nor backed up again
+        let current = "# kept\nschema_version: 1\nprofiles: {}\n";
+        fs::write(&path, current).unwrap();
+        fs::remove_file(directory.join("profiles.yaml.v0.bak")).unwrap();
+        ProfileConfig::read_or_create(path.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), current);
+        assert!(!directory.join("profiles.yaml.v0.bak").exists());
+
+        fs::write(&path, "schema_version: 99\nprofiles: {}\n").unwrap();
+        assert!(ProfileConfig::read_or_create(path).is_err());
+    }
+
+    #[test]
+    fn parses_config_without_profiles() {
+        for (format, content) in [
+            (ConfigFormat::Json, "{}"),
+            (ConfigFormat::Yaml, "schema_version: 1"),
+            (ConfigFormat::Toml, ""),
+        ] {
+            let config: ProfileConfig = format.parse(content).unwrap();
+            assert!(config.profiles.is_empty(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn round_trips_profiles() {
+        let profile: Profile = serde_json::from_value(json!({
+            "extends": ["base"],
+            "vars": { "world": "test" },
+            "layers": [
+                { "instance": { "version": "1.20.1", "loader": "fabric", "loader_version": null } },
+                { "variants": [
+                    { "modrinth_mod": { "project": "sodium", "version": null, "version_range": ">=0.5" } },
+                    { "matrix": { "axes": { "java": {
+                        "17": { "java": { "version": 17 } },
+                        "21": { "curseforge_mod": { "id": 394468, "file": null } },
+                    } } } },
+                ] },
+                { "maven_artifact": { "repo": "https://maven.test", "coordinates": "dev.test:mod:latest" } },
+            ],
+        }))
+        .unwrap();
+        let expected = serde_json::to_value(&profile).unwrap();
+        for format in [ConfigFormat::Json, ConfigFormat::Yaml, ConfigFormat::Toml] {
+            let content = format.serialize(&profile).unwrap();
+
+            let parsed: Profile = format.parse(&content).unwrap();
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                expected,
+                "{format:?}"
+            );
+        }
+        // TOML has no null, so absent values have to be left out
+        let toml = ConfigFormat::Toml.serialize(&profile).unwrap();
+        assert!(!toml.contains("loader_version"), "{toml}");
+        assert!(!toml.contains("target"), "{toml}");
+    }
+
+    #[test]
+    fn resolves_inheritance_and_fragments() {
+        let config = config(json!({
+            "profiles": {
+                "base": {
+                    "vars": { "mod": "base", "base": "1" },
+                    "layers": [{ "execute_command": "base" }],
+                },
+                "common": {
+                    "extends": ["base"],
+                    "layers": [{ "include": { "fragment": "setup" } }],
+                },
+                "base_first": { "extends": ["base", "common"], "layers": [] },
+                "child": {
+                    "extends": ["common", "base"],
+                    "vars": { "mod": "child" },
+                    "layers": [{ "variants": [
+                        { "include": { "fragment": "single" } },
+                        { "execute_command": "child" },
+                    ] }],
+                },
+            },
+            "fragments": {
+                "setup": [{ "execute_command": "setup" }, { "include": { "fragment": "single" } }],
+                "single": [{ "execute_command": "single" }],
+            },
+        }));
+        let profile = config.resolve_profile("child").unwrap();
+        assert_eq!(profile.name, "child");
+        assert_eq!(
+            commands(&profile.layers),
+            ["base", "setup", "single", "variants single child"]
+        );
+        assert_eq!(profile.vars["mod"], "child");
+        assert_eq!(profile.vars["base"], "1");
+        // Shared ancestors are applied once, where they are first reached
+        assert_eq!(
+            commands(&config.resolve_profile("base_first").unwrap().layers),
+            ["base", "setup", "single"]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_inheritance_and_fragments() {
+        let config = config(json!({
+            "profiles": {
+                "a": { "extends": ["b"], "layers": [] },
+                "b": { "extends": ["a"], "layers": [] },
+                "missing_parent": { "extends": ["none"], "layers": [] },
+                "missing_fragment": { "layers": [{ "include": { "fragment": "none" } }] },
+                "cyclic_fragment": { "layers": [{ "include": { "fragment": "loop" } }] },
+                "nested_fragment": { "layers": [{ "variants": [{ "include": { "fragment": "two" } }] }] },
+            },
+            "fragments": {
+                "loop": [{ "include": { "fragment": "loop" } }],
+                "two": [{ "execute_command": "a" }, { "execute_command": "b" }],
+            },
+        }));
+        for (name, message) in [
+            ("a", "cyclic inheritance: a -> b -> a"),
+            (
+                "missing_parent",
+                "Profile `missing_parent` extends `none`, which does not exist",
+            ),
+            ("missing_fragment", "Fragment `none` does not exist"),
+            (
+                "cyclic_fragment",
+                "Fragment `loop` includes itself: loop -> loop",
+            ),
+            ("nested_fragment", "has to consist of exactly one layer"),
+            ("unknown", "Profile does not exist"),
+        ] {
+            let Err(error) = config.resolve_profile(name) else {
+                panic!("{name} resolved");
+            };
+            let error = format!("{error:#}");
+            assert!(error.contains(message), "{name}: {error}");
+        }
+        // Fragments which were never included are reported instead of being skipped
+        let unresolved = config.profiles["missing_fragment"].clone();
+        assert!(unresolved.get_variants("test_".to_owned()).is_err());
     }
 }
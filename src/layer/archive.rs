@@ -0,0 +1,62 @@
+use std::{
+    fs,
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use zip::ZipArchive;
+
+pub fn open(path: &Path) -> Result<ZipArchive<fs::File>> {
+    ZipArchive::new(fs::File::open(path).context(format!("Unable to open {}", path.display()))?)
+        .context(format!("{} is not a valid zip archive", path.display()))
+}
+
+pub fn contains<R: Read + Seek>(archive: &ZipArchive<R>, name: &str) -> bool {
+    archive.file_names().any(|it| it == name)
+}
+
+/// Reads and deserializes the json file `name` from the archive
+pub fn read_json<T: DeserializeOwned, R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<T> {
+    let mut content = String::new();
+    archive
+        .by_name(name)
+        .context(format!("Archive does not contain {name}"))?
+        .read_to_string(&mut content)?;
+    serde_json::from_str(&content).context(format!("{name} format invalid"))
+}
+
+/// Extracts all entries below the directory `prefix` of the archive into `target`
+pub fn extract_dir<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    prefix: &str,
+    target: &Path,
+) -> Result<()> {
+    let prefix = format!("{}/", prefix.trim_end_matches('/'));
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        // enclosed_name rejects entries escaping the target directory
+        let Some(relative) = entry
+            .enclosed_name()
+            .and_then(|it| it.strip_prefix(&prefix).ok())
+            .map(Path::to_path_buf)
+        else {
+            continue;
+        };
+        let destination = target.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&destination)?;
+            continue;
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut fs::File::create(&destination)?)
+            .context(format!("Unable to extract {}", destination.display()))?;
+    }
+    Ok(())
+}
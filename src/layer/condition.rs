@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use helixlauncher_core::launch::instance;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{ModloaderDef, ResolvedLayer};
+use crate::version::VersionReq;
+
+/// A predicate over the layers preceding a `when` layer.
+/// Game version and loader are taken from the last instance layer, instances created by packs are not known beforehand
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Loader(#[serde(with = "ModloaderDef")] instance::Modloader),
+    /// A requirement on the game version, e.g. `>=1.20` or `1.19.x`
+    GameVersion(String),
+    /// Whether a layer of the given type, e.g. `modrinth_mod`, precedes this one
+    HasLayer(String),
+    /// Whether the given value was chosen for a matrix axis
+    Axis {
+        axis: String,
+        value: String,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn matches(
+        &self,
+        previous_layers: &[ResolvedLayer],
+        axes: &BTreeMap<String, String>,
+    ) -> bool {
+        let instance = previous_layers.iter().rev().find_map(|it| match it {
+            ResolvedLayer::Instance {
+                version, loader, ..
+            } => Some((version, *loader)),
+            _ => None,
+        });
+        match self {
+            Self::Loader(expected) => instance.is_some_and(|(_, loader)| loader == *expected),
+            Self::GameVersion(requirement) => instance.is_some_and(|(version, _)| {
+                VersionReq::parse(requirement).is_ok_and(|it| it.matches(version))
+            }),
+            Self::HasLayer(kind) => previous_layers.iter().any(|it| it.kind() == *kind),
+            Self::Axis { axis, value } => axes.get(axis) == Some(value),
+            Self::All(conditions) => conditions
+                .iter()
+                .all(|it| it.matches(previous_layers, axes)),
+            Self::Any(conditions) => conditions
+                .iter()
+                .any(|it| it.matches(previous_layers, axes)),
+            Self::Not(condition) => !condition.matches(previous_layers, axes),
+        }
+    }
+
+    /// Checks the version requirements of this condition, so invalid ones don't silently never match
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::GameVersion(requirement) => VersionReq::parse(requirement)
+                .map(|_| ())
+                .context(format!("Invalid game version condition `{requirement}`")),
+            Self::All(conditions) | Self::Any(conditions) => {
+                conditions.iter().try_for_each(Self::validate)
+            }
+            Self::Not(condition) => condition.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use helixlauncher_core::launch::instance;
+    use serde_json::json;
+
+    use super::Condition;
+    use crate::layer::ResolvedLayer;
+
+    fn condition(value: serde_json::Value) -> Condition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn instance(version: &str, loader: instance::Modloader) -> ResolvedLayer {
+        ResolvedLayer::Instance {
+            version: version.to_owned(),
+            loader,
+            loader_version: None,
+        }
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let layers = [
+            instance("1.19.2", instance::Modloader::Forge),
+            ResolvedLayer::ExecuteCommand("true".to_owned()),
+            instance("1.20.1", instance::Modloader::Fabric),
+        ];
+        let axes = BTreeMap::from([("side".to_owned(), "client".to_owned())]);
+        for (value, expected) in [
+            (json!({ "loader": "fabric" }), true),
+            (json!({ "loader": "forge" }), false),
+            (json!({ "game_version": ">=1.20" }), true),
+            (json!({ "game_version": "1.19.x" }), false),
+            (json!({ "has_layer": "execute_command" }), true),
+            (json!({ "has_layer": "modrinth_mod" }), false),
+            (
+                json!({ "axis": { "axis": "side", "value": "client" } }),
+                true,
+            ),
+            (
+                json!({ "axis": { "axis": "side", "value": "server" } }),
+                false,
+            ),
+            (
+                json!({ "axis": { "axis": "other", "value": "client" } }),
+                false,
+            ),
+            (
+                json!({ "all": [{ "loader": "fabric" }, { "game_version": "1.20.1" }] }),
+                true,
+            ),
+            (
+                json!({ "all": [{ "loader": "fabric" }, { "game_version": "1.20" }] }),
+                false,
+            ),
+            (json!({ "all": [] }), true),
+            (
+                json!({ "any": [{ "loader": "forge" }, { "has_layer": "execute_command" }] }),
+                true,
+            ),
+            (json!({ "any": [] }), false),
+            (json!({ "not": { "loader": "forge" } }), true),
+        ] {
+            assert_eq!(
+                condition(value.clone()).matches(&layers, &axes),
+                expected,
+                "{value}"
+            );
+        }
+        // Without a preceding instance neither the loader nor the game version are known
+        for value in [
+            json!({ "loader": "vanilla" }),
+            json!({ "game_version": ">=1.0" }),
+        ] {
+            assert!(!condition(value).matches(&layers[1..2], &axes));
+        }
+    }
+
+    #[test]
+    fn validates_version_requirements() {
+        assert!(condition(json!({ "game_version": ">=1.20" }))
+            .validate()
+            .is_ok());
+        for value in [
+            json!({ "game_version": "" }),
+            json!({ "not": { "any": [{ "game_version": ">=1.19," }] } }),
+        ] {
+            assert!(condition(value.clone()).validate().is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use std::{collections::HashMap, env, path::Path};
+
+use anyhow::{bail, Context, Result};
+use helixlauncher_core::launch::instance;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    archive,
+    download::{Downloader, Hash},
+    GameInfo,
+};
+use crate::lock::LockedFile;
+
+const DEFAULT_API_URL: &str = "https://api.curseforge.com";
+const API_KEY_VAR: &str = "CURSEFORGE_API_KEY";
+
+/// The CurseForge api base url, can be overridden using `CURSEFORGE_API_URL`
+fn api_url() -> String {
+    env::var("CURSEFORGE_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_owned())
+}
+
+pub struct Client {
+    downloader: Downloader,
+    api_key: String,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+    pub id: u32,
+    pub mod_id: u32,
+    pub file_name: String,
+    pub file_date: String,
+    pub download_url: Option<String>,
+    #[serde(default)]
+    pub hashes: Vec<FileHash>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FileHash {
+    pub value: String,
+    pub algo: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Mod {
+    id: u32,
+    class_id: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub minecraft: ManifestMinecraft,
+    pub files: Vec<ManifestFile>,
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_owned()
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestMinecraft {
+    pub version: String,
+    #[serde(default)]
+    pub mod_loaders: Vec<ManifestModLoader>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ManifestModLoader {
+    pub id: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u32,
+    #[serde(rename = "fileID")]
+    pub file_id: u32,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+const SHA1_ALGO: u32 = 1;
+const RESOURCE_PACK_CLASS: u32 = 12;
+const SHADER_PACK_CLASS: u32 = 6552;
+
+impl File {
+    fn sha1(&self) -> Option<String> {
+        self.hashes
+            .iter()
+            .find(|it| it.algo == SHA1_ALGO)
+            .map(|it| it.value.clone())
+    }
+
+    fn download_url(&self) -> Result<&String> {
+        self.download_url.as_ref().context(format!(
+            "{} (project {}) can't be downloaded through the api as its author disallowed third party distribution",
+            self.file_name, self.mod_id
+        ))
+    }
+
+    /// Downloads the file to `directory`
+    pub async fn download(&self, client: &Client, directory: &Path) -> Result<()> {
+        client
+            .downloader
+            .download(
+                self.download_url()?,
+                &directory.join(&self.file_name),
+                self.sha1().map(Hash::Sha1).as_ref(),
+            )
+            .await
+    }
+
+    /// The file as it is pinned in the lock, so later runs can download it without the api
+    pub fn lock(&self) -> Result<LockedFile> {
+        Ok(LockedFile {
+            project_id: self.mod_id.to_string(),
+            version_id: self.id.to_string(),
+            filename: self.file_name.clone(),
+            url: self.download_url()?.clone(),
+            sha1: self
+                .sha1()
+                .context(format!("{} has no sha1 hash", self.file_name))?,
+        })
+    }
+}
+
+impl Manifest {
+    /// The game version and mod loader declared by the manifest
+    pub fn game(&self) -> Result<GameInfo> {
+        let loader = self
+            .minecraft
+            .mod_loaders
+            .iter()
+            .find(|it| it.primary)
+            .or(self.minecraft.mod_loaders.first());
+        let (loader, loader_version) = match loader {
+            None => (instance::Modloader::Vanilla, None),
+            Some(loader) => match loader.id.split_once('-') {
+                Some(("forge", version)) => (instance::Modloader::Forge, Some(version.to_owned())),
+                Some(("fabric", version)) => {
+                    (instance::Modloader::Fabric, Some(version.to_owned()))
+                }
+                Some(("quilt", version)) => (instance::Modloader::Quilt, Some(version.to_owned())),
+                _ => bail!("Unsupported mod loader {}", loader.id),
+            },
+        };
+        Ok(GameInfo {
+            version: self.minecraft.version.clone(),
+            loader,
+            loader_version,
+        })
+    }
+}
+
+fn mod_loader_type(loader: instance::Modloader) -> Result<u32> {
+    Ok(match loader {
+        instance::Modloader::Forge => 1,
+        instance::Modloader::Fabric => 4,
+        instance::Modloader::Quilt => 5,
+        instance::Modloader::Vanilla => bail!("Mods can't be installed without a mod loader"),
+    })
+}
+
+impl Client {
+    pub fn new(downloader: Downloader) -> Result<Self> {
+        Ok(Self {
+            downloader,
+            api_key: env::var(API_KEY_VAR).context(format!(
+                "A CurseForge api key has to be provided using the {API_KEY_VAR} environment variable"
+            ))?,
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        Ok(self
+            .downloader
+            .client
+            .get(format!("{}{path}", api_url()))
+            .header("x-api-key", &self.api_key)
+            .query(query)
+            .send()
+            .await
+            .and_then(|it| it.error_for_status())
+            .context(format!("CurseForge request {path} failed"))?
+            .json::<Response<T>>()
+            .await
+            .context(format!("Invalid CurseForge response for {path}"))?
+            .data)
+    }
+
+    async fn post<T: DeserializeOwned, B: Serialize>(&self, path: &str, body: &B) -> Result<T> {
+        Ok(self
+            .downloader
+            .client
+            .post(format!("{}{path}", api_url()))
+            .header("x-api-key", &self.api_key)
+            .json(body)
+            .send()
+            .await
+            .and_then(|it| it.error_for_status())
+            .context(format!("CurseForge request {path} failed"))?
+            .json::<Response<T>>()
+            .await
+            .context(format!("Invalid CurseForge response for {path}"))?
+            .data)
+    }
+
+    pub async fn file(&self, project: u32, file: u32) -> Result<File> {
+        self.get(&format!("/v1/mods/{project}/files/{file}"), &[])
+            .await
+    }
+
+    /// Finds the newest file of `project`, restricted to the game version and loader of `game` if given
+    pub async fn latest_file(&self, project: u32, game: Option<&GameInfo>) -> Result<File> {
+        let mut query = vec![];
+        if let Some(game) = game {
+            query.push(("gameVersion", game.version.clone()));
+            query.push(("modLoaderType", mod_loader_type(game.loader)?.to_string()));
+        }
+        let files: Vec<File> = self
+            .get(&format!("/v1/mods/{project}/files"), &query)
+            .await?;
+        files
+            .into_iter()
+            .max_by(|a, b| a.file_date.cmp(&b.file_date))
+            .context(match game {
+                Some(game) => format!(
+                    "No file of CurseForge project {project} compatible with Minecraft {} ({}) was found",
+                    game.version, game.loader
+                ),
+                None => format!("CurseForge project {project} has no files"),
+            })
+    }
+
+    /// Installs all files and overrides of the pack at `pack` into `game_dir`
+    pub async fn install_pack(
+        &self,
+        manifest: &Manifest,
+        pack: &Path,
+        game_dir: &Path,
+    ) -> Result<()> {
+        let file_ids: Vec<u32> = manifest
+            .files
+            .iter()
+            .filter(|it| it.required)
+            .map(|it| it.file_id)
+            .collect();
+        let mod_ids: Vec<u32> = manifest
+            .files
+            .iter()
+            .filter(|it| it.required)
+            .map(|it| it.project_id)
+            .collect();
+        let files: Vec<File> = self
+            .post("/v1/mods/files", &HashMap::from([("fileIds", file_ids)]))
+            .await?;
+        let classes: HashMap<u32, Option<u32>> = self
+            .post::<Vec<Mod>, _>("/v1/mods", &HashMap::from([("modIds", mod_ids)]))
+            .await?
+            .into_iter()
+            .map(|it| (it.id, it.class_id))
+            .collect();
+        futures::future::try_join_all(files.iter().map(|file| {
+            let directory = match classes.get(&file.mod_id).copied().flatten() {
+                Some(RESOURCE_PACK_CLASS) => game_dir.join("resourcepacks"),
+                Some(SHADER_PACK_CLASS) => game_dir.join("shaderpacks"),
+                _ => game_dir.join("mods"),
+            };
+            async move { file.download(self, &directory).await }
+        }))
+        .await?;
+        archive::extract_dir(&mut archive::open(pack)?, &manifest.overrides, game_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs};
+
+    use helixlauncher_core::launch::instance;
+    use serde_json::json;
+    use sha1::Digest;
+
+    use super::{Client, Manifest, API_KEY_VAR};
+    use crate::layer::{
+        download::Downloader,
+        test_util::{temp_dir, write_zip, TestServer},
+        GameInfo,
+    };
+
+    fn manifest(mod_loaders: serde_json::Value) -> Manifest {
+        serde_json::from_value(json!({
+            "minecraft": { "version": "1.20.1", "modLoaders": mod_loaders },
+            "files": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn maps_manifest_loaders() {
+        for (mod_loaders, loader, loader_version) in [
+            (json!([]), instance::Modloader::Vanilla, None),
+            (
+                json!([{ "id": "forge-47.1.0", "primary": true }]),
+                instance::Modloader::Forge,
+                Some("47.1.0"),
+            ),
+            (
+                json!([{ "id": "fabric-0.14.21" }, { "id": "quilt-0.19.2", "primary": true }]),
+                instance::Modloader::Quilt,
+                Some("0.19.2"),
+            ),
+            (
+                json!([{ "id": "fabric-0.14.21" }, { "id": "quilt-0.19.2" }]),
+                instance::Modloader::Fabric,
+                Some("0.14.21"),
+            ),
+        ] {
+            let game = manifest(mod_loaders).game().unwrap();
+            assert_eq!(game.version, "1.20.1");
+            assert_eq!(game.loader, loader);
+            assert_eq!(game.loader_version.as_deref(), loader_version);
+        }
+        assert!(
+            manifest(json!([{ "id": "neoforge-20.2.86", "primary": true }]))
+                .game()
+                .is_err()
+        );
+        assert!(manifest(json!([{ "id": "forge", "primary": true }]))
+            .game()
+            .is_err());
+    }
+
+    fn file(url: &str, mod_id: u32, name: &str, date: &str) -> serde_json::Value {
+        json!({
+            "id": mod_id * 10,
+            "modId": mod_id,
+            "fileName": name,
+            "fileDate": date,
+            "downloadUrl": format!("{url}/files/{name}"),
+            "hashes": [
+                { "value": "ignored", "algo": 2 },
+                { "value": hex::encode(sha1::Sha1::digest(name)), "algo": 1 },
+            ],
+        })
+    }
+
+    /// A mock of the CurseForge api, each file contains its own name
+    async fn mock_api() -> TestServer {
+        TestServer::start(|url| {
+            let responses = [
+                (
+                    "/v1/mods/1/files",
+                    json!([
+                        file(url, 1, "old.jar", "2023-06-01T00:00:00Z"),
+                        file(url, 1, "new.jar", "2023-07-01T00:00:00Z"),
+                    ]),
+                ),
+                ("/v1/mods/2/files", json!([])),
+                (
+                    "/v1/mods/files",
+                    json!([
+                        file(url, 10, "mod.jar", "2023-06-01T00:00:00Z"),
+                        file(url, 11, "resources.zip", "2023-06-01T00:00:00Z"),
+                        file(url, 12, "shaders.zip", "2023-06-01T00:00:00Z"),
+                    ]),
+                ),
+                (
+                    "/v1/mods",
+                    json!([
+                        { "id": 10, "classId": 6 },
+                        { "id": 11, "classId": 12 },
+                        { "id": 12, "classId": 6552 },
+                    ]),
+                ),
+            ];
+            let files = [
+                "old.jar",
+                "new.jar",
+                "mod.jar",
+                "resources.zip",
+                "shaders.zip",
+            ]
+            .map(|it| (format!("/files/{it}"), it.as_bytes().to_vec()));
+            responses
+                .into_iter()
+                .map(|(path, body)| {
+                    (
+                        path.to_owned(),
+                        json!({ "data": body }).to_string().into_bytes(),
+                    )
+                })
+                .chain(files)
+                .collect::<HashMap<_, _>>()
+        })
+        .await
+    }
+
+    /// The only test changing `CURSEFORGE_API_URL` and the api key, so tests running in parallel don't interfere
+    #[tokio::test]
+    async fn resolves_and_installs_from_mock_api() {
+        let server = mock_api().await;
+        let directory = temp_dir("curseforge");
+        let downloader = || Downloader::new(directory.join("cache")).unwrap();
+        env::remove_var(API_KEY_VAR);
+        let error = Client::new(downloader()).err().unwrap();
+        assert!(format!("{error:#}").contains(API_KEY_VAR), "{error:#}");
+        env::set_var(API_KEY_VAR, "key");
+        env::set_var("CURSEFORGE_API_URL", &server.url);
+        let client = Client::new(downloader()).unwrap();
+
+        let game = GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Fabric,
+            loader_version: None,
+        };
+        let latest = client.latest_file(1, Some(&game)).await.unwrap();
+        assert_eq!(latest.file_name, "new.jar");
+        let query = server.requests()[0].clone();
+        assert_eq!(query, "/v1/mods/1/files?gameVersion=1.20.1&modLoaderType=4");
+        client.latest_file(1, None).await.unwrap();
+        assert_eq!(server.requests()[1], "/v1/mods/1/files");
+        assert!(client.latest_file(2, Some(&game)).await.is_err());
+        let vanilla = GameInfo {
+            loader: instance::Modloader::Vanilla,
+            ..game
+        };
+        assert!(client.latest_file(1, Some(&vanilla)).await.is_err());
+
+        let pack = directory.join("pack.zip");
+        write_zip(
+            &pack,
+            &[
+                ("manifest.json", b"{}"),
+                ("overrides/config/mod.toml", b"option = true"),
+            ],
+        );
+        let manifest: Manifest = serde_json::from_value(json!({
+            "minecraft": { "version": "1.20.1" },
+            "files": [
+                { "projectID": 10, "fileID": 100 },
+                { "projectID": 11, "fileID": 110 },
+                { "projectID": 12, "fileID": 120, "required": true },
+            ],
+        }))
+        .unwrap();
+        let game_dir = directory.join("game");
+        client
+            .install_pack(&manifest, &pack, &game_dir)
+            .await
+            .unwrap();
+        for (path, content) in [
+            ("mods/mod.jar", "mod.jar"),
+            ("resourcepacks/resources.zip", "resources.zip"),
+            ("shaderpacks/shaders.zip", "shaders.zip"),
+            ("config/mod.toml", "option = true"),
+        ] {
+            assert_eq!(fs::read_to_string(game_dir.join(path)).unwrap(), content);
+        }
+        assert!(!game_dir.join("manifest.json").exists());
+    }
+}
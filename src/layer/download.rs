@@ -0,0 +1,147 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{ensure, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha1::Digest;
+
+use crate::lock::LockedFile;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Hash {
+    Sha1(String),
+    Sha512(String),
+}
+
+impl Hash {
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        let (expected, actual) = match self {
+            Self::Sha1(expected) => (expected, hex::encode(sha1::Sha1::digest(data))),
+            Self::Sha512(expected) => (expected, hex::encode(sha2::Sha512::digest(data))),
+        };
+        ensure!(
+            expected.eq_ignore_ascii_case(&actual),
+            "Hash mismatch: expected {expected}, got {actual}"
+        );
+        Ok(())
+    }
+
+    /// The location of the file with this hash inside the download cache
+    fn cache_path(&self, cache_directory: &Path) -> PathBuf {
+        let (algorithm, hash) = match self {
+            Self::Sha1(hash) => ("sha1", hash),
+            Self::Sha512(hash) => ("sha512", hash),
+        };
+        cache_directory
+            .join("downloads")
+            .join(algorithm)
+            .join(hash.to_lowercase())
+    }
+}
+
+static PARTIAL_DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Downloads files, storing those with a known hash in a cache shared by all variants
+#[derive(Clone)]
+pub struct Downloader {
+    pub client: reqwest::Client,
+    cache_directory: PathBuf,
+}
+
+impl Downloader {
+    pub fn new(cache_directory: PathBuf) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .user_agent(concat!(
+                    env!("CARGO_PKG_NAME"),
+                    "/",
+                    env!("CARGO_PKG_VERSION")
+                ))
+                .build()
+                .context("Unable to create http client")?,
+            cache_directory,
+        })
+    }
+
+    /// Downloads `url` to `target`, creating parent directories.
+    /// If `hash` is given the download is verified and cached, so later downloads of the same file are copied from the cache
+    pub async fn download(&self, url: &str, target: &Path, hash: Option<&Hash>) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let Some(hash) = hash else {
+            let data = self.fetch(url).await?;
+            return fs::write(target, data)
+                .context(format!("Unable to write {}", target.display()));
+        };
+        let cached = hash.cache_path(&self.cache_directory);
+        if !cached.is_file() {
+            let data = self.fetch(url).await?;
+            hash.verify(&data)
+                .context(format!("Downloaded file {url} is corrupted"))?;
+            store(&cached, &data)?;
+        }
+        fs::copy(&cached, target).context(format!("Unable to write {}", target.display()))?;
+        Ok(())
+    }
+
+    /// Downloads a previously locked file into `directory`, verifying it against the locked hash
+    pub async fn download_locked(&self, file: &LockedFile, directory: &Path) -> Result<()> {
+        self.download(
+            &file.url,
+            &directory.join(&file.filename),
+            Some(&Hash::Sha1(file.sha1.clone())),
+        )
+        .await
+    }
+
+    /// Downloads `url` to `target` without verifying it, caching it under `key`.
+    /// Only suitable for files which never change once published, as cached files are reused without asking the server
+    pub async fn download_unverified(&self, url: &str, target: &Path, key: &Path) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let cached = self
+            .cache_directory
+            .join("downloads")
+            .join("unverified")
+            .join(key);
+        if !cached.is_file() {
+            store(&cached, &self.fetch(url).await?)?;
+        }
+        fs::copy(&cached, target).context(format!("Unable to write {}", target.display()))?;
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        Ok(self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|it| it.error_for_status())
+            .context(format!("Unable to download {url}"))?
+            .bytes()
+            .await
+            .context(format!("Unable to download {url}"))?
+            .to_vec())
+    }
+}
+
+/// Writes `data` to a temporary file first, so concurrently running variants never see a partial file
+fn store(cached: &Path, data: &[u8]) -> Result<()> {
+    fs::create_dir_all(cached.parent().unwrap())?;
+    let temporary = cached.with_extension(format!(
+        "{}-{}.part",
+        std::process::id(),
+        PARTIAL_DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, cached)?;
+    Ok(())
+}
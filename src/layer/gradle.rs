@@ -0,0 +1,244 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use sha1::{Digest, Sha1};
+use tokio::{process::Command, sync::Mutex};
+
+/// Output directories of a gradle project or subproject which don't influence the build result.
+/// Directories starting with a dot, like `.gradle` or `.git`, are ignored as well
+const IGNORED_DIRECTORIES: &[&str] = &["build", "run", "out"];
+
+/// Files marking a directory as gradle (sub)project
+const BUILD_FILES: &[&str] = &[
+    "build.gradle",
+    "build.gradle.kts",
+    "settings.gradle",
+    "settings.gradle.kts",
+];
+
+/// Builds are serialized so variants sharing a project don't build it concurrently
+static BUILD_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub struct Build<'a> {
+    pub project_dir: &'a Path,
+    pub task: &'a str,
+    pub artifact_glob: &'a str,
+    pub offline: bool,
+}
+
+impl Build<'_> {
+    /// Builds the project unless a build of the same sources is cached, returning the built artifact.
+    /// Build output is appended to `log`
+    pub async fn run(&self, cache_directory: &Path, log: &Path) -> Result<PathBuf> {
+        let _guard = BUILD_LOCK.lock().await;
+        let fingerprint = self.fingerprint()?;
+        let cached = cache_directory.join("gradle").join(&fingerprint);
+        let mut log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .context("Unable to open setup log")?;
+        if let Some(artifact) = find_artifact(&cached, "*")? {
+            writeln!(
+                log,
+                "Using cached build {fingerprint} of {}",
+                self.project_dir.display()
+            )?;
+            return Ok(artifact);
+        }
+
+        let wrapper = fs::canonicalize(self.project_dir)?.join(if cfg!(target_os = "windows") {
+            "gradlew.bat"
+        } else {
+            "gradlew"
+        });
+        let mut command = Command::new(&wrapper);
+        command.current_dir(self.project_dir).arg(self.task);
+        if self.offline {
+            command.arg("--offline");
+        }
+        let output = command
+            .output()
+            .await
+            .context(format!("Unable to run {}", wrapper.display()))?;
+        log.write_all(&output.stdout)?;
+        log.write_all(&output.stderr)?;
+        ensure!(
+            output.status.success(),
+            "Gradle task {} failed, see the setup log for details",
+            self.task
+        );
+
+        let artifact = find_artifact(self.project_dir, self.artifact_glob)?.context(format!(
+            "No artifact matching {} was produced",
+            self.artifact_glob
+        ))?;
+        fs::create_dir_all(&cached)?;
+        let cached_artifact = cached.join(artifact.file_name().unwrap());
+        fs::copy(&artifact, &cached_artifact)?;
+        Ok(cached_artifact)
+    }
+
+    /// A hash over the paths and contents of all source files of the project
+    fn fingerprint(&self) -> Result<String> {
+        let mut files = vec![];
+        collect_files(self.project_dir, self.project_dir, &mut files)?;
+        files.sort();
+        let mut hasher = Sha1::new();
+        for file in files {
+            hasher.update(file.to_string_lossy().as_bytes());
+            hasher.update(fs::read(self.project_dir.join(&file))?);
+        }
+        hasher.update(self.task.as_bytes());
+        hasher.update(self.artifact_glob.as_bytes());
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+fn collect_files(root: &Path, directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    // Only output directories of projects are skipped, a source package may be named `build` as well
+    let is_project = directory == root || BUILD_FILES.iter().any(|it| directory.join(it).is_file());
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || (is_project && IGNORED_DIRECTORIES.contains(&name.as_str()))
+            {
+                continue;
+            }
+            collect_files(root, &entry.path(), files)?;
+        } else {
+            files.push(entry.path().strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Finds the single file matching `glob` relative to `directory`, ignoring source and javadoc jars
+fn find_artifact(directory: &Path, glob: &str) -> Result<Option<PathBuf>> {
+    let (glob_directory, pattern) = match glob.rsplit_once('/') {
+        Some((glob_directory, pattern)) => (directory.join(glob_directory), pattern),
+        None => (directory.to_path_buf(), glob),
+    };
+    if !glob_directory.is_dir() {
+        return Ok(None);
+    }
+    let mut matches = vec![];
+    for entry in fs::read_dir(glob_directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_file()
+            && glob_match(pattern, &name)
+            && !name.ends_with("-sources.jar")
+            && !name.ends_with("-javadoc.jar")
+        {
+            matches.push(entry.path());
+        }
+    }
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop()),
+        _ => {
+            bail!("Multiple artifacts match {glob}, use a more specific artifact glob: {matches:?}")
+        }
+    }
+}
+
+/// Matches `name` against a pattern supporting `*` and `?` wildcards
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len())
+            .filter(|it| name.is_char_boundary(*it))
+            .any(|it| glob_match(&pattern[1..], &name[it..])),
+        Some('?') => {
+            let mut chars = name.chars();
+            chars.next().is_some() && glob_match(&pattern[1..], chars.as_str())
+        }
+        Some(char) => {
+            name.starts_with(char)
+                && glob_match(&pattern[char.len_utf8()..], &name[char.len_utf8()..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{find_artifact, glob_match, Build};
+    use crate::layer::test_util::temp_dir;
+
+    #[test]
+    fn matches_globs() {
+        for (pattern, name, expected) in [
+            ("*.jar", "mod-1.0.jar", true),
+            ("*.jar", "mod-1.0.jar.sha1", false),
+            ("mod-*.jar", "mod-1.0.jar", true),
+            ("mod-*.jar", "other-1.0.jar", false),
+            ("mod-?.?.jar", "mod-1.0.jar", true),
+            ("mod-?.?.jar", "mod-1.10.jar", false),
+            ("*", "", true),
+            ("?", "", false),
+            ("*-dev*.jar", "mod-1.0-dev.jar", true),
+            ("*ä*", "mäd.jar", true),
+        ] {
+            assert_eq!(glob_match(pattern, name), expected, "{pattern} {name}");
+        }
+    }
+
+    #[test]
+    fn finds_single_artifact() {
+        let directory = temp_dir("gradle-artifact");
+        let libs = directory.join("build").join("libs");
+        fs::create_dir_all(&libs).unwrap();
+        for file in ["mod-1.0.jar", "mod-1.0-sources.jar", "mod-1.0-javadoc.jar"] {
+            fs::write(libs.join(file), "").unwrap();
+        }
+        assert_eq!(
+            find_artifact(&directory, "build/libs/*.jar").unwrap(),
+            Some(libs.join("mod-1.0.jar"))
+        );
+        assert_eq!(find_artifact(&directory, "missing/*.jar").unwrap(), None);
+        fs::write(libs.join("mod-1.0-dev.jar"), "").unwrap();
+        assert!(find_artifact(&directory, "build/libs/*.jar").is_err());
+    }
+
+    #[test]
+    fn fingerprints_only_sources() {
+        let directory = temp_dir("gradle-fingerprint");
+        let build = Build {
+            project_dir: &directory,
+            task: "build",
+            artifact_glob: "build/libs/*.jar",
+            offline: true,
+        };
+        let package = directory.join("sub/src/main/java/dev/test/build");
+        fs::create_dir_all(&package).unwrap();
+        fs::write(directory.join("settings.gradle"), "include 'sub'").unwrap();
+        fs::write(directory.join("sub/build.gradle"), "").unwrap();
+        fs::write(package.join("Mod.java"), "class Mod {}").unwrap();
+        let fingerprint = build.fingerprint().unwrap();
+
+        for output in [
+            "build/libs/a.jar",
+            ".gradle/a.bin",
+            "sub/build/libs/a.jar",
+            "sub/out/a.class",
+            "sub/.gradle/a.bin",
+        ] {
+            let output = directory.join(output);
+            fs::create_dir_all(output.parent().unwrap()).unwrap();
+            fs::write(output, "output").unwrap();
+        }
+        assert_eq!(build.fingerprint().unwrap(), fingerprint);
+
+        // A package named like an output directory is still part of the sources
+        fs::write(package.join("Other.java"), "class Other {}").unwrap();
+        assert_ne!(build.fingerprint().unwrap(), fingerprint);
+    }
+}
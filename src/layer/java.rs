@@ -0,0 +1,227 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{ensure, Context, Result};
+use serde::Serialize;
+
+use super::archive;
+use crate::version;
+
+/// A java installation the game can be launched with
+#[derive(Serialize, Clone, Debug)]
+pub struct JavaRuntime {
+    /// The java executable
+    pub path: PathBuf,
+    pub version: String,
+    pub major: u32,
+}
+
+fn executable_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "java.exe"
+    } else {
+        "java"
+    }
+}
+
+impl JavaRuntime {
+    /// Probes the java executable at `path`, or inside the `bin` directory if `path` is a java home
+    pub fn probe(path: &Path) -> Result<Self> {
+        let path = if path.is_dir() {
+            path.join("bin").join(executable_name())
+        } else {
+            path.to_path_buf()
+        };
+        let output = Command::new(&path)
+            .arg("-version")
+            .output()
+            .context(format!("Unable to run {}", path.display()))?;
+        let (version, major) = parse_version(&String::from_utf8_lossy(&output.stderr)).context(
+            format!("Unable to determine the version of {}", path.display()),
+        )?;
+        Ok(Self {
+            path,
+            version,
+            major,
+        })
+    }
+
+    /// Finds an installed java runtime with the given major version
+    pub fn find(major: u32, cache_directory: &Path) -> Result<Self> {
+        candidates(cache_directory)
+            .iter()
+            .filter_map(|it| Self::probe(it).ok())
+            .find(|it| it.major == major)
+            .context(format!(
+                "No java {major} installation was found, provide one using `path` or `archive`"
+            ))
+    }
+
+    /// Extracts a jdk archive into the cache unless already done and probes it
+    pub fn provision(archive_path: &Path, cache_directory: &Path) -> Result<Self> {
+        let name = archive_path
+            .file_name()
+            .context("Invalid java archive path")?
+            .to_string_lossy()
+            .to_string();
+        let target = cache_directory.join("java").join(&name);
+        if !target.is_dir() {
+            let partial = cache_directory.join("java").join(format!("{name}.part"));
+            if partial.exists() {
+                fs::remove_dir_all(&partial)?;
+            }
+            fs::create_dir_all(&partial)?;
+            if name.ends_with(".zip") {
+                archive::open(archive_path)?
+                    .extract(&partial)
+                    .context(format!("Unable to extract {}", archive_path.display()))?;
+            } else {
+                ensure!(
+                    Command::new("tar")
+                        .arg("-xf")
+                        .arg(archive_path)
+                        .arg("-C")
+                        .arg(&partial)
+                        .status()
+                        .context("Unable to run tar")?
+                        .success(),
+                    "Unable to extract {}",
+                    archive_path.display()
+                );
+            }
+            fs::rename(&partial, &target)?;
+        }
+        java_homes(&target)
+            .iter()
+            .find_map(|it| Self::probe(it).ok())
+            .context(format!(
+                "{} does not contain a java runtime",
+                archive_path.display()
+            ))
+    }
+}
+
+/// The directory itself if it is a java home, otherwise all java homes directly inside it
+fn java_homes(directory: &Path) -> Vec<PathBuf> {
+    if directory.join("bin").join(executable_name()).is_file() {
+        return vec![directory.to_path_buf()];
+    }
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|it| it.ok())
+        .map(|it| it.path())
+        .flat_map(|it| [it.join("Contents").join("Home"), it])
+        .filter(|it| it.join("bin").join(executable_name()).is_file())
+        .collect()
+}
+
+/// The version and its major version from the output of `java -version`, which is printed to stderr,
+/// e.g. `openjdk version "17.0.8" 2023-07-18`. Versions before java 9 look like `1.8.0_372`
+fn parse_version(output: &str) -> Result<(String, u32)> {
+    let version = output
+        .split('"')
+        .nth(1)
+        .context("`java -version` printed no version")?
+        .to_owned();
+    let major = match version.strip_prefix("1.") {
+        Some(legacy) => legacy.split('.').next(),
+        None => version.split(['.', '-', '+']).next(),
+    }
+    .and_then(|it| it.parse().ok())
+    .context(format!("Unrecognized java version {version}"))?;
+    Ok((version, major))
+}
+
+fn candidates(cache_directory: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![];
+    if let Some(java_home) = env::var_os("JAVA_HOME") {
+        candidates.push(PathBuf::from(java_home));
+    }
+    let mut search = vec![
+        cache_directory.join("java"),
+        PathBuf::from("/usr/lib/jvm"),
+        PathBuf::from("/Library/Java/JavaVirtualMachines"),
+        PathBuf::from("C:\\Program Files\\Java"),
+        PathBuf::from("C:\\Program Files\\Eclipse Adoptium"),
+    ];
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        search.push(PathBuf::from(home).join(".jdks"));
+    }
+    for directory in search {
+        candidates.extend(java_homes(&directory));
+    }
+    candidates.push(PathBuf::from(executable_name()));
+    candidates
+}
+
+/// The minimum java major version required by a minecraft release
+pub fn required_major(game_version: &str) -> Option<u32> {
+    // Only releases can be compared, snapshots like `23w31a` are skipped
+    if !game_version.starts_with("1.") {
+        return None;
+    }
+    Some(if version::compare(game_version, "1.17").is_lt() {
+        8
+    } else if version::compare(game_version, "1.18").is_lt() {
+        16
+    } else if version::compare(game_version, "1.20.5").is_lt() {
+        17
+    } else {
+        21
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_version, required_major};
+
+    #[test]
+    fn requires_java_by_game_version() {
+        for (game_version, major) in [
+            ("1.8.9", Some(8)),
+            ("1.16.5", Some(8)),
+            ("1.17", Some(16)),
+            ("1.17.1", Some(16)),
+            ("1.18", Some(17)),
+            ("1.20.4", Some(17)),
+            ("1.20.5", Some(21)),
+            ("1.21", Some(21)),
+            ("23w31a", None),
+            ("b1.7.3", None),
+            ("", None),
+        ] {
+            assert_eq!(required_major(game_version), major, "{game_version}");
+        }
+    }
+
+    #[test]
+    fn parses_version_output() {
+        for (output, version, major) in [
+            (
+                "openjdk version \"1.8.0_372\"\nOpenJDK Runtime Environment (build 1.8.0_372-b07)",
+                "1.8.0_372",
+                8,
+            ),
+            (
+                "openjdk version \"17.0.8\" 2023-07-18\nOpenJDK Runtime Environment Temurin-17.0.8+7",
+                "17.0.8",
+                17,
+            ),
+            ("java version \"21\" 2023-09-19 LTS", "21", 21),
+            ("openjdk version \"22-ea\" 2024-03-19", "22-ea", 22),
+        ] {
+            assert_eq!(
+                parse_version(output).unwrap(),
+                (version.to_owned(), major),
+                "{output}"
+            );
+        }
+        assert!(parse_version("command not found").is_err());
+        assert!(parse_version("openjdk version \"unknown\"").is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use sha1::Digest;
+
+use super::download::{Downloader, Hash};
+use crate::lock::LockedFile;
+
+/// A parsed `group:artifact:version[:classifier]` coordinate
+pub struct Coordinates {
+    group: String,
+    artifact: String,
+    version: String,
+    classifier: Option<String>,
+}
+
+impl Coordinates {
+    pub fn parse(coordinates: &str) -> Result<Self> {
+        let parts: Vec<&str> = coordinates.split(':').collect();
+        let (group, artifact, version, classifier) = match parts.as_slice() {
+            [group, artifact, version] => (group, artifact, version, None),
+            [group, artifact, version, classifier] => {
+                (group, artifact, version, Some(classifier.to_string()))
+            }
+            _ => bail!(
+                "Invalid maven coordinates `{coordinates}`, expected group:artifact:version[:classifier]"
+            ),
+        };
+        Ok(Self {
+            group: group.to_string(),
+            artifact: artifact.to_string(),
+            version: version.to_string(),
+            classifier,
+        })
+    }
+
+    /// Whether the version is resolved anew on each run, as it is `latest` or a `-SNAPSHOT`
+    pub fn is_dynamic(&self) -> bool {
+        self.version == "latest" || self.version.ends_with("-SNAPSHOT")
+    }
+
+    fn artifact_url(&self, repo: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            repo.trim_end_matches('/'),
+            self.group.replace('.', "/"),
+            self.artifact
+        )
+    }
+}
+
+async fn fetch_text(downloader: &Downloader, url: &str) -> Result<Option<String>> {
+    let response = downloader
+        .client
+        .get(url)
+        .send()
+        .await
+        .context(format!("Unable to fetch {url}"))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(
+        response
+            .error_for_status()
+            .context(format!("Unable to fetch {url}"))?
+            .text()
+            .await?,
+    ))
+}
+
+/// The inner text of all `tag` elements in `xml`
+fn xml_tags<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut found = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(rest[..end].trim());
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn xml_tag<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    xml_tags(xml, tag).into_iter().next()
+}
+
+/// Resolves `latest` to the newest published version of the artifact
+async fn resolve_version(
+    downloader: &Downloader,
+    repo: &str,
+    coordinates: &Coordinates,
+) -> Result<String> {
+    if coordinates.version != "latest" {
+        return Ok(coordinates.version.clone());
+    }
+    let url = format!("{}/maven-metadata.xml", coordinates.artifact_url(repo));
+    let metadata = fetch_text(downloader, &url)
+        .await?
+        .context(format!("No maven metadata found at {url}"))?;
+    xml_tag(&metadata, "latest")
+        .or_else(|| xml_tags(&metadata, "version").last().copied())
+        .map(str::to_owned)
+        .context(format!(
+            "Maven metadata at {url} does not list any versions"
+        ))
+}
+
+/// Resolves a `-SNAPSHOT` version to the version string of the newest snapshot build used in file names
+async fn resolve_snapshot(
+    downloader: &Downloader,
+    repo: &str,
+    coordinates: &Coordinates,
+    version: &str,
+) -> Result<String> {
+    let Some(base) = version.strip_suffix("-SNAPSHOT") else {
+        return Ok(version.to_owned());
+    };
+    let url = format!(
+        "{}/{version}/maven-metadata.xml",
+        coordinates.artifact_url(repo)
+    );
+    let Some(metadata) = fetch_text(downloader, &url).await? else {
+        // Repositories without unique snapshot versions
+        return Ok(version.to_owned());
+    };
+    let snapshot_version = xml_tags(&metadata, "snapshotVersion")
+        .into_iter()
+        .find(|it| {
+            xml_tag(it, "extension") == Some("jar")
+                && xml_tag(it, "classifier") == coordinates.classifier.as_deref()
+        })
+        .and_then(|it| xml_tag(it, "value"));
+    if let Some(value) = snapshot_version {
+        return Ok(value.to_owned());
+    }
+    match (
+        xml_tag(&metadata, "timestamp"),
+        xml_tag(&metadata, "buildNumber"),
+    ) {
+        (Some(timestamp), Some(build_number)) => Ok(format!("{base}-{timestamp}-{build_number}")),
+        _ => Ok(version.to_owned()),
+    }
+}
+
+/// Downloads the artifact from `repo` into `directory`, returning the resolved file to pin in the lock
+pub async fn install(
+    downloader: &Downloader,
+    repo: &str,
+    coordinates: &Coordinates,
+    directory: &Path,
+) -> Result<LockedFile> {
+    let version = resolve_version(downloader, repo, coordinates).await?;
+    let file_version = resolve_snapshot(downloader, repo, coordinates, &version).await?;
+    let file_name = match &coordinates.classifier {
+        Some(classifier) => format!("{}-{file_version}-{classifier}.jar", coordinates.artifact),
+        None => format!("{}-{file_version}.jar", coordinates.artifact),
+    };
+    let url = format!("{}/{version}/{file_name}", coordinates.artifact_url(repo));
+    let target = directory.join(&file_name);
+    // Repositories publish checksums next to each file, which also keys the download cache
+    let sha1 = fetch_text(downloader, &format!("{url}.sha1"))
+        .await?
+        .and_then(|it| {
+            it.split_whitespace()
+                .next()
+                .map(|it| Hash::Sha1(it.to_owned()))
+        });
+    match &sha1 {
+        Some(sha1) => downloader.download(&url, &target, Some(sha1)).await?,
+        None => {
+            eprintln!("Warning: {url} has no published checksum and is used unverified");
+            // Non-unique snapshots are replaced in place, so they can't be cached
+            if file_version.ends_with("-SNAPSHOT") {
+                downloader.download(&url, &target, None).await?
+            } else {
+                let key = Path::new(&coordinates.group)
+                    .join(&coordinates.artifact)
+                    .join(&version)
+                    .join(&file_name);
+                downloader.download_unverified(&url, &target, &key).await?
+            }
+        }
+    }
+    let sha1 = match sha1 {
+        Some(Hash::Sha1(sha1)) => sha1,
+        _ => hex::encode(sha1::Sha1::digest(fs::read(&target)?)),
+    };
+    Ok(LockedFile {
+        project_id: format!("{}:{}", coordinates.group, coordinates.artifact),
+        version_id: file_version,
+        filename: file_name,
+        url,
+        sha1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use sha1::Digest;
+
+    use super::{install, xml_tags, Coordinates};
+    use crate::layer::{
+        download::Downloader,
+        test_util::{temp_dir, TestServer},
+    };
+
+    const SNAPSHOT_METADATA: &str = "<metadata><versioning><snapshotVersions>
+        <snapshotVersion><classifier>sources</classifier><extension>jar</extension><value>1.0-20230701.120000-3</value></snapshotVersion>
+        <snapshotVersion><extension>jar</extension><value>1.0-20230701.120000-3</value></snapshotVersion>
+        <snapshotVersion><extension>pom</extension><value>1.0-20230701.120000-3</value></snapshotVersion>
+    </snapshotVersions></versioning></metadata>";
+
+    fn routes(files: &[(&str, &str)]) -> HashMap<String, Vec<u8>> {
+        files
+            .iter()
+            .map(|(path, body)| {
+                (
+                    format!("/maven/dev/test/mod/{path}"),
+                    body.as_bytes().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_coordinates() {
+        let coordinates = Coordinates::parse("dev.test:mod:1.0:dev").unwrap();
+        assert_eq!(coordinates.classifier.as_deref(), Some("dev"));
+        assert_eq!(
+            coordinates.artifact_url("https://maven.test/releases/"),
+            "https://maven.test/releases/dev/test/mod"
+        );
+        assert!(Coordinates::parse("dev.test:mod").is_err());
+        assert!(Coordinates::parse("dev.test:mod:1.0:dev:extra").is_err());
+        assert!(!coordinates.is_dynamic());
+        for dynamic in ["dev.test:mod:latest", "dev.test:mod:1.0-SNAPSHOT:dev"] {
+            assert!(
+                Coordinates::parse(dynamic).unwrap().is_dynamic(),
+                "{dynamic}"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_xml_tags() {
+        let xml = "<versions><version>1.0</version><version> 1.1 </version></versions><version>2.0";
+        assert_eq!(xml_tags(xml, "version"), ["1.0", "1.1"]);
+        assert!(xml_tags(xml, "latest").is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolves_latest_and_snapshots() {
+        let jar = "snapshot jar";
+        let server = TestServer::start(|_| {
+            routes(&[
+                (
+                    "maven-metadata.xml",
+                    "<metadata><versioning><versions><version>0.9</version><version>1.0-SNAPSHOT</version></versions></versioning></metadata>",
+                ),
+                ("1.0-SNAPSHOT/maven-metadata.xml", SNAPSHOT_METADATA),
+                ("1.0-SNAPSHOT/mod-1.0-20230701.120000-3.jar", jar),
+                (
+                    "1.0-SNAPSHOT/mod-1.0-20230701.120000-3.jar.sha1",
+                    &hex::encode(sha1::Sha1::digest(jar)),
+                ),
+            ])
+        })
+        .await;
+        let directory = temp_dir("maven-snapshot");
+        let downloader = Downloader::new(directory.join("cache")).unwrap();
+        let repo = format!("{}/maven", server.url);
+        for coordinates in ["dev.test:mod:latest", "dev.test:mod:1.0-SNAPSHOT"] {
+            let file = install(
+                &downloader,
+                &repo,
+                &Coordinates::parse(coordinates).unwrap(),
+                &directory.join("mods"),
+            )
+            .await
+            .unwrap();
+            assert_eq!(file.filename, "mod-1.0-20230701.120000-3.jar");
+            assert_eq!(file.version_id, "1.0-20230701.120000-3");
+            assert_eq!(file.sha1, hex::encode(sha1::Sha1::digest(jar)));
+            assert_eq!(
+                fs::read_to_string(directory.join("mods").join(file.filename)).unwrap(),
+                jar
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_artifacts_without_checksum() {
+        let directory = temp_dir("maven-unverified");
+        let downloader = Downloader::new(directory.join("cache")).unwrap();
+        let coordinates = Coordinates::parse("dev.test:mod:1.0").unwrap();
+        let server = TestServer::start(|_| routes(&[("1.0/mod-1.0.jar", "release jar")])).await;
+        // Without a published checksum the downloaded jar is hashed for the lock
+        let file = install(
+            &downloader,
+            &format!("{}/maven", server.url),
+            &coordinates,
+            &directory.join("first"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(file.sha1, hex::encode(sha1::Sha1::digest("release jar")));
+        // The second install is served from the cache, as the repository does not have the jar
+        let empty = TestServer::start(|_| HashMap::new()).await;
+        install(
+            &downloader,
+            &format!("{}/maven", empty.url),
+            &coordinates,
+            &directory.join("second"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("second").join("mod-1.0.jar")).unwrap(),
+            "release jar"
+        );
+        assert_eq!(
+            empty.requests(),
+            ["/maven/dev/test/mod/1.0/mod-1.0.jar.sha1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_corrupted_artifacts() {
+        let server = TestServer::start(|_| {
+            routes(&[
+                ("1.0/mod-1.0.jar", "tampered jar"),
+                (
+                    "1.0/mod-1.0.jar.sha1",
+                    &hex::encode(sha1::Sha1::digest("release jar")),
+                ),
+            ])
+        })
+        .await;
+        let directory = temp_dir("maven-corrupted");
+        let downloader = Downloader::new(directory.join("cache")).unwrap();
+        assert!(install(
+            &downloader,
+            &format!("{}/maven", server.url),
+            &Coordinates::parse("dev.test:mod:1.0").unwrap(),
+            &directory.join("mods"),
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use std::{cmp::Ordering, collections::HashMap, env, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use helixlauncher_core::launch::instance;
+use serde::{Deserialize, Serialize};
+
+use super::{download::Downloader, SetupContext};
+use crate::version::{self, VersionReq};
+
+const DEFAULT_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VersionManifest {
+    pub latest: LatestVersions,
+    /// All versions, newest first
+    pub versions: Vec<ManifestVersion>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LatestVersions {
+    pub release: String,
+    pub snapshot: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManifestVersion {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub version_type: String,
+}
+
+impl VersionManifest {
+    /// Fetches the version manifest, falling back to the cached one when offline or if fetching fails
+    pub async fn get(context: &SetupContext) -> Result<Self> {
+        let cached = context.cache_directory.join("version_manifest_v2.json");
+        if !context.offline {
+            match Self::fetch(&Downloader::new(context.cache_directory.clone())?).await {
+                Ok(manifest) => {
+                    fs::create_dir_all(&context.cache_directory)?;
+                    fs::write(&cached, serde_json::to_string(&manifest)?)?;
+                    return Ok(manifest);
+                }
+                Err(e) if cached.is_file() => {
+                    eprintln!("Unable to fetch version manifest, using cached one: {e:#}")
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Self::read(&cached)
+    }
+
+    async fn fetch(downloader: &Downloader) -> Result<Self> {
+        let url =
+            env::var("MINECRAFT_MANIFEST_URL").unwrap_or_else(|_| DEFAULT_MANIFEST_URL.to_owned());
+        downloader
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|it| it.error_for_status())
+            .context("Unable to fetch version manifest")?
+            .json()
+            .await
+            .context("Version manifest format invalid")
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        serde_json::from_str(
+            &fs::read_to_string(path)
+                .context("No cached version manifest is available, run once without --offline")?,
+        )
+        .context("Cached version manifest format invalid")
+    }
+
+    /// Compares versions by their position in the manifest, so snapshots are ordered correctly
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let position = |id: &str| self.versions.iter().position(|it| it.id == id);
+        match (position(a), position(b)) {
+            (Some(a), Some(b)) => b.cmp(&a),
+            _ => version::compare(a, b),
+        }
+    }
+
+    /// The versions matching `query`, oldest first. `query` is `latest-release`, `latest-snapshot`
+    /// or a version requirement like `1.20.x` or `1.19.2..=1.20.1`. Snapshots and other
+    /// non-release versions only match if `snapshots` is set or they are named explicitly
+    pub fn select(&self, query: &str, snapshots: bool) -> Result<Vec<String>> {
+        let versions: Vec<String> = match query {
+            "latest-release" => vec![self.latest.release.clone()],
+            "latest-snapshot" => vec![self.latest.snapshot.clone()],
+            query => {
+                let requirement = VersionReq::parse(query)?;
+                self.versions
+                    .iter()
+                    .rev()
+                    .filter(|it| snapshots || it.version_type == "release" || it.id == query)
+                    .filter(|it| requirement.matches_with(&it.id, |a, b| self.compare(a, b)))
+                    .map(|it| it.id.clone())
+                    .collect()
+            }
+        };
+        if versions.is_empty() {
+            bail!("No minecraft version matches `{query}`");
+        }
+        Ok(versions)
+    }
+}
+
+const DEFAULT_FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2";
+const DEFAULT_QUILT_META_URL: &str = "https://meta.quiltmc.org/v3";
+const DEFAULT_FORGE_PROMOTIONS_URL: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+const DEFAULT_FORGE_METADATA_URL: &str =
+    "https://maven.minecraftforge.net/net/minecraftforge/forge/maven-metadata.xml";
+
+/// The loader meta url `default`, can be overridden using the environment variable `var`
+fn meta_url(var: &str, default: &str) -> String {
+    env::var(var).unwrap_or_else(|_| default.to_owned())
+}
+
+/// Whether `loader_version` is a query like `latest`, `latest-stable` or `>=0.14` instead of an exact version
+pub fn is_loader_query(loader_version: &str) -> bool {
+    matches!(loader_version, "latest" | "latest-stable")
+        || loader_version.contains(['<', '>', '=', ',', '*'])
+        || loader_version.contains("..")
+        || loader_version.ends_with(".x")
+}
+
+#[derive(Deserialize)]
+struct LoaderEntry {
+    loader: LoaderVersion,
+}
+
+#[derive(Deserialize)]
+struct LoaderVersion {
+    version: String,
+    stable: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ForgePromotions {
+    promos: HashMap<String, String>,
+}
+
+/// Available loader versions for a game version, newest first, paired with whether they are stable
+pub async fn loader_versions(
+    context: &SetupContext,
+    loader: instance::Modloader,
+    game_version: &str,
+) -> Result<Vec<(String, bool)>> {
+    let downloader = Downloader::new(context.cache_directory.clone())?;
+    Ok(match loader {
+        instance::Modloader::Fabric | instance::Modloader::Quilt => {
+            let base = if loader == instance::Modloader::Fabric {
+                meta_url("FABRIC_META_URL", DEFAULT_FABRIC_META_URL)
+            } else {
+                meta_url("QUILT_META_URL", DEFAULT_QUILT_META_URL)
+            };
+            let entries: Vec<LoaderEntry> = downloader
+                .client
+                .get(format!("{base}/versions/loader/{game_version}"))
+                .send()
+                .await
+                .and_then(|it| it.error_for_status())
+                .context(format!("Unable to fetch {loader} versions"))?
+                .json()
+                .await
+                .context(format!("Invalid {loader} version list"))?;
+            entries
+                .into_iter()
+                .map(|it| {
+                    // Quilt meta has no stable flag, its unstable versions are marked as pre-releases
+                    let stable = it.loader.stable.unwrap_or(!it.loader.version.contains('-'));
+                    (it.loader.version, stable)
+                })
+                .collect()
+        }
+        instance::Modloader::Forge => {
+            let promotions: ForgePromotions = downloader
+                .client
+                .get(meta_url(
+                    "FORGE_PROMOTIONS_URL",
+                    DEFAULT_FORGE_PROMOTIONS_URL,
+                ))
+                .send()
+                .await
+                .and_then(|it| it.error_for_status())
+                .context("Unable to fetch forge promotions")?
+                .json()
+                .await
+                .context("Invalid forge promotions")?;
+            // Without a recommended build for the game version, its latest promotion counts as stable
+            let promoted = promotions
+                .promos
+                .get(&format!("{game_version}-recommended"))
+                .or_else(|| promotions.promos.get(&format!("{game_version}-latest")));
+            let metadata = downloader
+                .client
+                .get(meta_url("FORGE_METADATA_URL", DEFAULT_FORGE_METADATA_URL))
+                .send()
+                .await
+                .and_then(|it| it.error_for_status())
+                .context("Unable to fetch forge versions")?
+                .text()
+                .await?;
+            let prefix = format!("{game_version}-");
+            let mut versions: Vec<(String, bool)> = metadata
+                .split("<version>")
+                .skip(1)
+                .filter_map(|it| it.split("</version>").next())
+                .filter_map(|it| it.strip_prefix(&prefix))
+                // Old versions carry the game version as suffix too, e.g. 1.12.2-14.23.5.2860-1.12.2
+                .map(|it| it.split('-').next().unwrap_or(it).to_owned())
+                .map(|it| {
+                    let stable = promoted.is_some_and(|promoted| {
+                        version::compare(&it, promoted) != Ordering::Greater
+                    });
+                    (it, stable)
+                })
+                .collect();
+            versions.sort_by(|a, b| version::compare(&b.0, &a.0));
+            versions
+        }
+        instance::Modloader::Vanilla => bail!("Vanilla has no loader versions"),
+    })
+}
+
+/// Resolves a loader version query for the given game version to a concrete version
+pub async fn resolve_loader_version(
+    context: &SetupContext,
+    loader: instance::Modloader,
+    game_version: &str,
+    query: &str,
+) -> Result<String> {
+    let versions = loader_versions(context, loader, game_version).await?;
+    let requirement = match query {
+        "latest" | "latest-stable" => None,
+        query => Some(VersionReq::parse(query)?),
+    };
+    versions
+        .into_iter()
+        .find(|(version, stable)| match &requirement {
+            None => query == "latest" || *stable,
+            Some(requirement) => requirement.matches(version),
+        })
+        .map(|(version, _)| version)
+        .context(format!(
+            "No {loader} version matching `{query}` is available for minecraft {game_version}"
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs};
+
+    use serde_json::json;
+
+    use helixlauncher_core::launch::instance;
+
+    use super::{is_loader_query, resolve_loader_version, VersionManifest};
+    use crate::layer::{
+        test_util::{temp_dir, TestServer},
+        SetupContext,
+    };
+
+    fn manifest() -> serde_json::Value {
+        let versions = [
+            ("23w31a", "snapshot"),
+            ("1.20.1", "release"),
+            ("1.20.1-rc1", "snapshot"),
+            ("1.20", "release"),
+            ("1.19.4", "release"),
+            ("23w03a", "snapshot"),
+            ("1.19.3", "release"),
+            ("1.19.2", "release"),
+            ("1.19.1", "release"),
+        ];
+        json!({
+            "latest": { "release": "1.20.1", "snapshot": "23w31a" },
+            "versions": versions
+                .iter()
+                .map(|(id, version_type)| json!({ "id": id, "type": version_type }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    #[test]
+    fn selects_versions() {
+        let manifest: VersionManifest = serde_json::from_value(manifest()).unwrap();
+        for (query, snapshots, expected) in [
+            ("latest-release", false, &["1.20.1"][..]),
+            ("latest-snapshot", false, &["23w31a"]),
+            (
+                "1.19.2..=1.20",
+                false,
+                &["1.19.2", "1.19.3", "1.19.4", "1.20"],
+            ),
+            ("1.19.3..1.20", false, &["1.19.3", "1.19.4"]),
+            ("1.20.x", false, &["1.20", "1.20.1"]),
+            (
+                "1.19.3..=1.20",
+                true,
+                &["1.19.3", "23w03a", "1.19.4", "1.20"],
+            ),
+            ("1.20.1-rc1", false, &["1.20.1-rc1"]),
+        ] {
+            assert_eq!(
+                manifest.select(query, snapshots).unwrap(),
+                expected,
+                "{query}"
+            );
+        }
+        assert!(manifest.select("1.21.x", false).is_err());
+        assert!(manifest.select("", false).is_err());
+    }
+
+    #[test]
+    fn detects_loader_queries() {
+        for query in [
+            "latest",
+            "latest-stable",
+            ">=0.14",
+            "0.14.x",
+            "0.14..0.15",
+            "*",
+        ] {
+            assert!(is_loader_query(query), "{query}");
+        }
+        for version in ["0.14.21", "47.1.0", "0.19.2-beta.3"] {
+            assert!(!is_loader_query(version), "{version}");
+        }
+    }
+
+    /// The only test changing `MINECRAFT_MANIFEST_URL`, so tests running in parallel don't interfere
+    #[tokio::test]
+    async fn caches_manifest_for_offline_use() {
+        let server = TestServer::start(|_| {
+            HashMap::from([(
+                "/manifest.json".to_owned(),
+                manifest().to_string().into_bytes(),
+            )])
+        })
+        .await;
+        env::set_var(
+            "MINECRAFT_MANIFEST_URL",
+            format!("{}/manifest.json", server.url),
+        );
+        let mut context = SetupContext {
+            cache_directory: temp_dir("manifest"),
+            offline: true,
+        };
+        assert!(VersionManifest::get(&context).await.is_err());
+
+        context.offline = false;
+        let fetched = VersionManifest::get(&context).await.unwrap();
+        assert_eq!(fetched.latest.release, "1.20.1");
+        assert_eq!(server.requests(), ["/manifest.json"]);
+
+        context.offline = true;
+        let cached = VersionManifest::get(&context).await.unwrap();
+        assert_eq!(cached.versions.len(), fetched.versions.len());
+        assert_eq!(server.requests().len(), 1);
+
+        // A failing fetch falls back to the cache
+        env::set_var(
+            "MINECRAFT_MANIFEST_URL",
+            format!("{}/missing.json", server.url),
+        );
+        context.offline = false;
+        assert!(VersionManifest::get(&context).await.is_ok());
+        fs::remove_dir_all(&context.cache_directory).unwrap();
+        assert!(VersionManifest::get(&context).await.is_err());
+    }
+
+    /// The only test changing the loader meta urls, so tests running in parallel don't interfere
+    #[tokio::test]
+    async fn resolves_loader_versions_from_mock_meta() {
+        let server = TestServer::start(|_| {
+            let forge_versions = [
+                "1.20.2-48.0.13",
+                "1.20.2-48.0.10",
+                "1.20.1-47.1.3",
+                "1.20.1-47.1.0",
+                "1.20.1-47.0.35",
+                "1.12.2-14.23.5.2860-1.12.2",
+            ]
+            .map(|it| format!("<version>{it}</version>"))
+            .concat();
+            [
+                (
+                    "/fabric/versions/loader/1.20.1",
+                    json!([
+                        { "loader": { "version": "0.14.22", "stable": false } },
+                        { "loader": { "version": "0.14.21", "stable": true } },
+                        { "loader": { "version": "0.14.20", "stable": true } },
+                    ])
+                    .to_string(),
+                ),
+                // Quilt meta has no stable flag
+                (
+                    "/quilt/versions/loader/1.20.1",
+                    json!([
+                        { "loader": { "version": "0.20.0-beta.1" } },
+                        { "loader": { "version": "0.19.2" } },
+                    ])
+                    .to_string(),
+                ),
+                (
+                    "/forge/promotions.json",
+                    json!({ "promos": {
+                        "1.20.1-recommended": "47.1.0",
+                        "1.20.1-latest": "47.1.3",
+                        "1.20.2-latest": "48.0.13",
+                    } })
+                    .to_string(),
+                ),
+                (
+                    "/forge/maven-metadata.xml",
+                    format!("<metadata><versioning><versions>{forge_versions}</versions></versioning></metadata>"),
+                ),
+            ]
+            .into_iter()
+            .map(|(path, body)| (path.to_owned(), body.into_bytes()))
+            .collect::<HashMap<_, _>>()
+        })
+        .await;
+        env::set_var("FABRIC_META_URL", format!("{}/fabric", server.url));
+        env::set_var("QUILT_META_URL", format!("{}/quilt", server.url));
+        env::set_var(
+            "FORGE_PROMOTIONS_URL",
+            format!("{}/forge/promotions.json", server.url),
+        );
+        env::set_var(
+            "FORGE_METADATA_URL",
+            format!("{}/forge/maven-metadata.xml", server.url),
+        );
+        let context = SetupContext {
+            cache_directory: temp_dir("loader-versions"),
+            offline: false,
+        };
+
+        for (loader, game_version, query, expected) in [
+            (instance::Modloader::Fabric, "1.20.1", "latest", "0.14.22"),
+            (
+                instance::Modloader::Fabric,
+                "1.20.1",
+                "latest-stable",
+                "0.14.21",
+            ),
+            (instance::Modloader::Fabric, "1.20.1", "<0.14.21", "0.14.20"),
+            (
+                instance::Modloader::Quilt,
+                "1.20.1",
+                "latest",
+                "0.20.0-beta.1",
+            ),
+            (
+                instance::Modloader::Quilt,
+                "1.20.1",
+                "latest-stable",
+                "0.19.2",
+            ),
+            (instance::Modloader::Forge, "1.20.1", "latest", "47.1.3"),
+            (
+                instance::Modloader::Forge,
+                "1.20.1",
+                "latest-stable",
+                "47.1.0",
+            ),
+            (instance::Modloader::Forge, "1.20.1", "<47.1", "47.0.35"),
+            // Without a recommended build the latest promotion is the newest stable version
+            (
+                instance::Modloader::Forge,
+                "1.20.2",
+                "latest-stable",
+                "48.0.13",
+            ),
+            (
+                instance::Modloader::Forge,
+                "1.12.2",
+                "latest",
+                "14.23.5.2860",
+            ),
+        ] {
+            assert_eq!(
+                resolve_loader_version(&context, loader, game_version, query)
+                    .await
+                    .unwrap(),
+                expected,
+                "{loader} {game_version} {query}"
+            );
+        }
+        for (loader, game_version, query) in [
+            // Neither a recommended nor a latest promotion
+            (instance::Modloader::Forge, "1.12.2", "latest-stable"),
+            (instance::Modloader::Fabric, "1.20.1", ">=0.15"),
+            (instance::Modloader::Fabric, "1.20.2", "latest"),
+            (instance::Modloader::Vanilla, "1.20.1", "latest"),
+        ] {
+            assert!(
+                resolve_loader_version(&context, loader, game_version, query)
+                    .await
+                    .is_err(),
+                "{loader} {game_version} {query}"
+            );
+        }
+    }
+}
@@ -0,0 +1,2091 @@
+use anyhow::{bail, ensure, Context, Ok, Result};
+use either::Either;
+use helixlauncher_core::{
+    auth::account::AccountConfig,
+    config::Config,
+    launch::{asset::merge_components, instance, prepared},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use crate::{
+    lock::{LockedFile, LockedVariant, ProfileLock},
+    report::VariantReport,
+    version::VersionReq,
+};
+use download::Downloader;
+
+mod archive;
+mod condition;
+pub use condition::Condition;
+mod curseforge;
+mod download;
+mod gradle;
+mod java;
+mod maven;
+pub(crate) mod meta;
+pub use download::Hash;
+pub use java::JavaRuntime;
+mod modrinth;
+mod mrpack;
+mod patch;
+mod template;
+#[cfg(test)]
+pub(crate) mod test_util;
+
+/// The directory inside an instance the game is run in
+pub const GAME_DIR: &str = ".minecraft";
+/// The log inside a variant directory output of setup steps is written to
+pub const SETUP_LOG: &str = "setup.log";
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct Profile {
+    #[serde(skip)]
+    pub name: String,
+    /// Profiles whose layers are applied before the layers of this profile, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    /// Variables usable as `${name}` in string fields of layers, next to `${game_version}`, `${loader}`,
+    /// `${loader_version}`, `${variant_name}` and `${env:NAME}`. `$${` is a literal `${`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(remote = "instance::Modloader")]
+#[serde(rename_all = "lowercase")]
+pub enum ModloaderDef {
+    Quilt,
+    Fabric,
+    Forge,
+    Vanilla,
+}
+
+/// Parses a mod loader by the name used in profiles, e.g. `fabric`
+pub fn parse_modloader(name: &str) -> Result<instance::Modloader> {
+    Ok(match name.to_lowercase().as_str() {
+        "quilt" => instance::Modloader::Quilt,
+        "fabric" => instance::Modloader::Fabric,
+        "forge" => instance::Modloader::Forge,
+        "vanilla" => instance::Modloader::Vanilla,
+        _ => bail!("Unknown mod loader `{name}`, expected quilt, fabric, forge or vanilla"),
+    })
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    DeleteDirectory(PathBuf),
+    Instance {
+        version: String,
+        #[serde(with = "ModloaderDef")]
+        loader: instance::Modloader,
+        loader_version: Option<String>,
+    },
+    /// Expands into one instance layer per minecraft version matching `versions`, which is
+    /// `latest-release`, `latest-snapshot` or a requirement like `1.20.x`, `>=1.19` or `1.19.2..=1.20.1`
+    InstanceRange {
+        versions: String,
+        #[serde(with = "ModloaderDef")]
+        loader: instance::Modloader,
+        loader_version: Option<String>,
+        /// Whether snapshots and other non-release versions are included
+        #[serde(default)]
+        snapshots: bool,
+    },
+    DirectoryOverlay {
+        source: PathBuf,
+    },
+    ModrinthPack {
+        id: String,
+        version: Option<String>,
+    },
+    /// Installs the newest version of a Modrinth project compatible with the game version and loader
+    ModrinthMod {
+        project: String,
+        /// An exact version number or id
+        version: Option<String>,
+        /// A requirement on the version number, e.g. `>=0.5, <0.6`
+        version_range: Option<String>,
+        #[serde(default)]
+        resolve_dependencies: bool,
+    },
+    /// Installs a CurseForge modpack, creating an instance for its game version and mod loader.
+    /// Requires an api key in the `CURSEFORGE_API_KEY` environment variable
+    #[serde(rename = "curseforge_pack")]
+    CurseForgePack {
+        id: u32,
+        /// The file id, the newest file is used and pinned in the lock if omitted
+        file: Option<u32>,
+    },
+    /// Installs the newest file of a CurseForge project compatible with the game version and loader.
+    /// Requires an api key in the `CURSEFORGE_API_KEY` environment variable
+    #[serde(rename = "curseforge_mod")]
+    CurseForgeMod {
+        id: u32,
+        /// The file id, the newest compatible file is used and pinned in the lock if omitted
+        file: Option<u32>,
+    },
+    /// Installs a local `.mrpack` or CurseForge modpack zip, creating an instance for its game version and mod loader
+    LocalPack {
+        path: PathBuf,
+    },
+    /// Downloads a file into the variant directory, verifying it against the given hash
+    Download {
+        url: String,
+        /// Relative to the variant directory, which it may not leave
+        target: PathBuf,
+        hash: Hash,
+    },
+    /// Downloads a `group:artifact:version[:classifier]` jar from a maven repository.
+    /// `-SNAPSHOT` versions resolve to the newest snapshot build and `latest` to the newest version,
+    /// which are pinned in the lock
+    MavenArtifact {
+        repo: String,
+        coordinates: String,
+        /// The directory the jar is downloaded into, relative to the variant directory. Defaults to the mods folder
+        target: Option<PathBuf>,
+    },
+    /// Builds a gradle project using its wrapper and installs the produced jar into the mods folder.
+    /// Builds are cached by a fingerprint of the project sources
+    GradleBuild {
+        project_dir: PathBuf,
+        /// The gradle task to run, defaults to `build`
+        task: Option<String>,
+        /// The produced jar relative to the project, defaults to `build/libs/*.jar`
+        artifact_glob: Option<String>,
+    },
+    /// Writes `content` to a file, replacing it if it exists
+    WriteFile {
+        target: PathBuf,
+        content: String,
+    },
+    /// Sets and removes keys of a `key=value` file like `server.properties`, `options.txt` uses `key:value`
+    PatchProperties {
+        target: PathBuf,
+        #[serde(default)]
+        set: BTreeMap<String, String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// Sets and removes values of a json or toml config by dot separated key paths, e.g. `client.render.fancy`
+    PatchConfig {
+        target: PathBuf,
+        #[serde(default)]
+        set: BTreeMap<String, serde_json::Value>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// Adds jvm arguments, memory settings and environment variables to the launch of the instance
+    LaunchConfig(LaunchConfig),
+    /// Selects the java runtime the game is launched with, either by major version from the installed ones,
+    /// by path, or by extracting a local jdk archive into the cache.
+    /// The runtime has to satisfy the requirement of the game version
+    Java {
+        version: Option<u32>,
+        path: Option<PathBuf>,
+        archive: Option<PathBuf>,
+    },
+    LaunchClient(LaunchOptions),
+    ExecuteCommand(String),
+    /// Inserts the layers of a fragment defined in the profile config
+    Include {
+        fragment: String,
+    },
+    Variants(Vec<Layer>),
+    /// Expands into one variant per combination of axis values, like a CI build matrix.
+    /// Combinations matching all values of an `exclude` entry are dropped, `include` entries
+    /// add combinations of existing values back. The chosen values are part of the variant name
+    Matrix {
+        axes: BTreeMap<String, BTreeMap<String, Layer>>,
+        #[serde(default)]
+        exclude: Vec<BTreeMap<String, String>>,
+        #[serde(default)]
+        include: Vec<BTreeMap<String, String>>,
+    },
+    IfPresent {
+        check_for: ResolvedLayer,
+        include: Box<Layer>,
+    },
+    IfNotPresent {
+        check_for: ResolvedLayer,
+        include: Box<Layer>,
+    },
+    /// Applies `then` if the condition holds for the preceding layers, `else` otherwise
+    When {
+        condition: Condition,
+        then: Box<Layer>,
+        #[serde(rename = "else", default, skip_serializing_if = "Option::is_none")]
+        otherwise: Option<Box<Layer>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolvedLayer {
+    DeleteDirectory(PathBuf),
+    Instance {
+        version: String,
+        #[serde(with = "ModloaderDef")]
+        loader: instance::Modloader,
+        loader_version: Option<String>,
+    },
+    DirectoryOverlay {
+        source: PathBuf,
+    },
+    ModrinthPack {
+        id: String,
+        version: Option<String>,
+    },
+    ModrinthMod {
+        project: String,
+        version: Option<String>,
+        version_range: Option<String>,
+        #[serde(default)]
+        resolve_dependencies: bool,
+    },
+    #[serde(rename = "curseforge_pack")]
+    CurseForgePack {
+        id: u32,
+        file: Option<u32>,
+    },
+    #[serde(rename = "curseforge_mod")]
+    CurseForgeMod {
+        id: u32,
+        file: Option<u32>,
+    },
+    LocalPack {
+        path: PathBuf,
+    },
+    Download {
+        url: String,
+        target: PathBuf,
+        hash: Hash,
+    },
+    MavenArtifact {
+        repo: String,
+        coordinates: String,
+        target: Option<PathBuf>,
+    },
+    GradleBuild {
+        project_dir: PathBuf,
+        task: Option<String>,
+        artifact_glob: Option<String>,
+    },
+    WriteFile {
+        target: PathBuf,
+        content: String,
+    },
+    PatchProperties {
+        target: PathBuf,
+        #[serde(default)]
+        set: BTreeMap<String, String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    PatchConfig {
+        target: PathBuf,
+        #[serde(default)]
+        set: BTreeMap<String, serde_json::Value>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    LaunchConfig(LaunchConfig),
+    Java {
+        version: Option<u32>,
+        path: Option<PathBuf>,
+        archive: Option<PathBuf>,
+    },
+    ExecuteCommand(String),
+    LaunchClient(LaunchOptions),
+}
+
+impl Layer {
+    /// Rewrites this layer and all layers nested inside it using `f`, innermost first
+    pub(crate) fn map_nested(self, f: &mut impl FnMut(Layer) -> Result<Layer>) -> Result<Layer> {
+        let layer = match self {
+            Self::Variants(variants) => Self::Variants(
+                variants
+                    .into_iter()
+                    .map(|it| it.map_nested(f))
+                    .collect::<Result<_>>()?,
+            ),
+            Self::IfPresent { check_for, include } => Self::IfPresent {
+                check_for,
+                include: Box::new(include.map_nested(f)?),
+            },
+            Self::IfNotPresent { check_for, include } => Self::IfNotPresent {
+                check_for,
+                include: Box::new(include.map_nested(f)?),
+            },
+            Self::When {
+                condition,
+                then,
+                otherwise,
+            } => Self::When {
+                condition,
+                then: Box::new(then.map_nested(f)?),
+                otherwise: match otherwise {
+                    Some(otherwise) => Some(Box::new(otherwise.map_nested(f)?)),
+                    None => None,
+                },
+            },
+            Self::Matrix {
+                axes,
+                exclude,
+                include,
+            } => Self::Matrix {
+                axes: axes
+                    .into_iter()
+                    .map(|(axis, values)| {
+                        let values = values
+                            .into_iter()
+                            .map(|(value, layer)| Ok((value, layer.map_nested(f)?)))
+                            .collect::<Result<_>>()?;
+                        Ok((axis, values))
+                    })
+                    .collect::<Result<_>>()?,
+                exclude,
+                include,
+            },
+            layer => layer,
+        };
+        f(layer)
+    }
+
+    /// Calls `f` for this layer and all layers nested inside it
+    fn visit(&self, f: &mut impl FnMut(&Layer)) {
+        f(self);
+        match self {
+            Self::Variants(variants) => variants.iter().for_each(|it| it.visit(f)),
+            Self::IfPresent { include, .. } | Self::IfNotPresent { include, .. } => {
+                include.visit(f)
+            }
+            Self::When {
+                then, otherwise, ..
+            } => {
+                then.visit(f);
+                if let Some(otherwise) = otherwise {
+                    otherwise.visit(f)
+                }
+            }
+            Self::Matrix { axes, .. } => axes
+                .values()
+                .flat_map(|it| it.values())
+                .for_each(|it| it.visit(f)),
+            _ => {}
+        }
+    }
+
+    /// The layers this layer stands for, one per variant. `axes` holds the values chosen for enclosing matrices
+    fn resolve(
+        self,
+        previous_layers: &[ResolvedLayer],
+        axes: &BTreeMap<String, String>,
+    ) -> Result<Vec<ResolvedLayer>> {
+        Ok(match self {
+            Self::DeleteDirectory(path) => vec![ResolvedLayer::DeleteDirectory(path)],
+            Self::Instance {
+                version,
+                loader,
+                loader_version,
+            } => vec![ResolvedLayer::Instance {
+                version,
+                loader,
+                loader_version,
+            }],
+            Self::InstanceRange { versions, .. } => {
+                bail!("Version range `{versions}` has to be expanded by Profile::expand first")
+            }
+            Self::Include { fragment } => {
+                bail!("Fragment `{fragment}` has to be included by ProfileConfig::resolve_profile first")
+            }
+            Self::Matrix { .. } => {
+                bail!("Matrix layers can't be nested inside variants or conditional layers")
+            }
+            Self::DirectoryOverlay { source } => {
+                vec![ResolvedLayer::DirectoryOverlay { source: source }]
+            }
+            Self::ModrinthPack { id, version } => vec![ResolvedLayer::ModrinthPack { id, version }],
+            Self::ModrinthMod {
+                project,
+                version,
+                version_range,
+                resolve_dependencies,
+            } => vec![ResolvedLayer::ModrinthMod {
+                project,
+                version,
+                version_range,
+                resolve_dependencies,
+            }],
+            Self::CurseForgePack { id, file } => vec![ResolvedLayer::CurseForgePack { id, file }],
+            Self::CurseForgeMod { id, file } => vec![ResolvedLayer::CurseForgeMod { id, file }],
+            Self::LocalPack { path } => vec![ResolvedLayer::LocalPack { path }],
+            Self::Download { url, target, hash } => {
+                vec![ResolvedLayer::Download { url, target, hash }]
+            }
+            Self::MavenArtifact {
+                repo,
+                coordinates,
+                target,
+            } => vec![ResolvedLayer::MavenArtifact {
+                repo,
+                coordinates,
+                target,
+            }],
+            Self::GradleBuild {
+                project_dir,
+                task,
+                artifact_glob,
+            } => vec![ResolvedLayer::GradleBuild {
+                project_dir,
+                task,
+                artifact_glob,
+            }],
+            Self::WriteFile { target, content } => {
+                vec![ResolvedLayer::WriteFile { target, content }]
+            }
+            Self::PatchProperties {
+                target,
+                set,
+                remove,
+            } => vec![ResolvedLayer::PatchProperties {
+                target,
+                set,
+                remove,
+            }],
+            Self::PatchConfig {
+                target,
+                set,
+                remove,
+            } => vec![ResolvedLayer::PatchConfig {
+                target,
+                set,
+                remove,
+            }],
+            Self::ExecuteCommand(command) => vec![ResolvedLayer::ExecuteCommand(command)],
+            Self::LaunchConfig(launch_config) => vec![ResolvedLayer::LaunchConfig(launch_config)],
+            Self::Java {
+                version,
+                path,
+                archive,
+            } => vec![ResolvedLayer::Java {
+                version,
+                path,
+                archive,
+            }],
+            Self::LaunchClient(launch_options) => vec![ResolvedLayer::LaunchClient(launch_options)],
+            Self::Variants(variants) => flatten(
+                variants
+                    .into_iter()
+                    .map(|e| e.resolve(previous_layers, axes)),
+            )?,
+            Self::IfPresent { check_for, include } => {
+                if previous_layers.contains(&check_for) {
+                    include.resolve(previous_layers, axes)?
+                } else {
+                    vec![]
+                }
+            }
+            Self::IfNotPresent { check_for, include } => {
+                if !previous_layers.contains(&check_for) {
+                    include.resolve(previous_layers, axes)?
+                } else {
+                    vec![]
+                }
+            }
+            Self::When {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if condition.matches(previous_layers, axes) {
+                    then.resolve(previous_layers, axes)?
+                } else if let Some(otherwise) = otherwise {
+                    otherwise.resolve(previous_layers, axes)?
+                } else {
+                    vec![]
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    layers: Vec<ResolvedLayer>,
+    name: String,
+    /// The value chosen for each matrix axis
+    axes: BTreeMap<String, String>,
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}:", self.name)?;
+        if !self.axes.is_empty() {
+            writeln!(
+                f,
+                "  axes: {}",
+                serde_json::to_string(&self.axes).map_err(|_| std::fmt::Error)?
+            )?;
+        }
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "  {}",
+                serde_json::to_string(layer).map_err(|_| std::fmt::Error)?
+            )?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+pub struct PreparedVariant {
+    instance: instance::Instance,
+    launch_options: LaunchOptions,
+    pub report: VariantReport,
+    pub lock: LockedVariant,
+    /// The game the variant was set up for, `None` if it has no instance
+    pub game: Option<GameInfo>,
+}
+
+/// Settings merged into the launch config of the instance. Multiple of these accumulate,
+/// later memory settings override earlier ones
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug, Default)]
+pub struct LaunchConfig {
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// System properties passed as `-Dkey=value`, e.g. `mixin.debug.export: "true"`
+    #[serde(default)]
+    pub system_properties: BTreeMap<String, String>,
+    /// The initial heap size, e.g. `512M`
+    pub min_memory: Option<String>,
+    /// The maximum heap size, e.g. `4G`
+    pub max_memory: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl LaunchConfig {
+    fn merge_into(&self, config: &mut instance::InstanceLaunchConfig) {
+        // Memory flags are replaced, so the last layer setting them wins
+        if let Some(min_memory) = &self.min_memory {
+            config.jvm_args.retain(|it| !it.starts_with("-Xms"));
+            config.jvm_args.push(format!("-Xms{min_memory}"));
+        }
+        if let Some(max_memory) = &self.max_memory {
+            config.jvm_args.retain(|it| !it.starts_with("-Xmx"));
+            config.jvm_args.push(format!("-Xmx{max_memory}"));
+        }
+        config.jvm_args.extend(
+            self.system_properties
+                .iter()
+                .map(|(key, value)| format!("-D{key}={value}")),
+        );
+        config.jvm_args.extend(self.jvm_args.iter().cloned());
+        config.env.extend(self.env.clone());
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+pub enum LaunchOptions {
+    Demo,
+    Offline {
+        account_name: Option<String>,
+        world_name: Option<String>,
+    },
+    Online {
+        account_name: Option<String>,
+        world_name: Option<String>,
+    },
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        Self::Online {
+            account_name: None,
+            world_name: None,
+        }
+    }
+}
+
+impl LaunchOptions {
+    fn into<'a>(self, account_config: &'a AccountConfig) -> Result<prepared::LaunchOptions<'a>> {
+        match self {
+            LaunchOptions::Demo => Ok(prepared::LaunchOptions::default()),
+            Self::Online {
+                account_name,
+                world_name,
+            } => {
+                ensure!(
+                    account_config.accounts.len() > 0,
+                    "Must be logged in to use non-demo accounts"
+                );
+                let account = match account_name {
+                    Some(account_name) => account_config
+                        .accounts
+                        .iter()
+                        .find(|it| it.username == account_name)
+                        .context(format!(
+                            "No account with the name matching {account_name} was found"
+                        ))?,
+                    None => account_config
+                        .selected()
+                        .context("No selected account was found")?,
+                };
+                return Ok(prepared::LaunchOptions::default()
+                    .account(Some(account))
+                    .world(world_name));
+            }
+            Self::Offline {
+                account_name: _,
+                world_name: _,
+            } => {
+                todo!();
+                // ensure!(
+                //     account_config.accounts.len() > 0,
+                //     "Must be logged in to use non-demo accounts"
+                // );
+                // let mut dummy = None;
+                // let account = match account_name {
+                //     Some(account_name) => {
+                //         let account = Account {
+                //             username: account_name.clone(),
+                //             uuid: "00000000-0000-0000-0000-000000000000".to_string(),
+                //             refresh_token: String::new(),
+                //             token: String::new(),
+                //         };
+                //         dummy = Some(account);
+                //         let account_ref = dummy.as_ref().unwrap();
+                //         account_config
+                //         .accounts
+                //         .iter()
+                //         .find(|it| it.username == account_name)
+                //         .or(Some(account_ref))
+                //         .unwrap()
+                //     }
+                //     None => account_config.selected()
+                //         .context("No selected account was found")?,
+                // };
+                // Ok((prepared::LaunchOptions::default()
+                //     .account(Some(account))
+                //     .world(world_name), dummy))
+            }
+        }
+    }
+}
+
+impl PreparedVariant {
+    pub async fn run(self, accounts: AccountConfig) -> Result<prepared::PreparedLaunch> {
+        let config = Config::new_with_data_dir(
+            "dev.helixlauncher.HelixLauncher",
+            "HelixLauncher",
+            self.instance.path.parent().unwrap().join(".helix_config"),
+        )?;
+        let merged_components = merge_components(&config, &self.instance.config.components).await?;
+        Ok(prepared::prepare_launch(
+            &config,
+            &self.instance,
+            &merged_components,
+            self.launch_options.into(&accounts)?,
+        )
+        .await?)
+    }
+}
+
+/// Game version and mod loader of the most recently applied [`ResolvedLayer::Instance`]
+#[derive(Debug, Clone)]
+pub struct GameInfo {
+    pub version: String,
+    pub loader: instance::Modloader,
+    pub loader_version: Option<String>,
+}
+
+/// Settings shared by the setup of all variants
+#[derive(Clone)]
+pub struct SetupContext {
+    /// Downloads and build results are cached here
+    pub cache_directory: PathBuf,
+    pub offline: bool,
+}
+
+/// The state a variant is in while its layers get applied
+pub struct SetupState {
+    instance: Either<instance::Instance, PathBuf>,
+    game: Option<GameInfo>,
+    launch_options: LaunchOptions,
+    launch_config: instance::InstanceLaunchConfig,
+    java: Option<JavaRuntime>,
+    /// Versions locked by a previous run
+    pinned: Option<LockedVariant>,
+    /// Versions chosen during this setup
+    lock: LockedVariant,
+    downloader: Downloader,
+    context: SetupContext,
+}
+
+impl SetupState {
+    fn path(&self) -> &PathBuf {
+        match &self.instance {
+            Either::Left(instance) => &instance.path,
+            Either::Right(path) => path,
+        }
+    }
+
+    fn game(&self) -> Result<&GameInfo> {
+        self.game
+            .as_ref()
+            .context("This layer requires a preceding instance layer")
+    }
+
+    /// The versions pinned by a previous run, unless that run used another game version or loader
+    fn pinned_for_game(&self) -> Option<&LockedVariant> {
+        let game = self.game.as_ref()?;
+        self.pinned.as_ref().filter(|it| {
+            it.game_version.as_ref() == Some(&game.version)
+                && it.loader == Some(game.loader.to_string())
+        })
+    }
+
+    /// The files pinned for the Modrinth mod layer `key` by a previous run of the same game
+    fn pinned_files(&self, key: &str) -> Option<&Vec<LockedFile>> {
+        self.pinned_for_game()?.mods.get(key)
+    }
+
+    /// Applies changes to the launch config to an already created instance
+    fn update_launch_config(&mut self) {
+        if let Either::Left(instance) = &mut self.instance {
+            instance.config.launch = self.launch_config.clone();
+        }
+    }
+
+    fn validate_java(&self) -> Result<()> {
+        let (Some(java), Some(game)) = (&self.java, &self.game) else {
+            return Ok(());
+        };
+        if let Some(required) = java::required_major(&game.version) {
+            ensure!(
+                java.major >= required,
+                "Minecraft {} requires java {required}, but java {} was selected",
+                game.version,
+                java.major
+            );
+        }
+        Ok(())
+    }
+
+    fn create_instance(&mut self, game: GameInfo) -> Result<()> {
+        let path = self.path().clone();
+        self.instance = Either::Left(
+            instance::Instance::new(
+                path.file_name().unwrap().to_string_lossy().to_string(),
+                game.version.clone(),
+                self.launch_config.clone(),
+                path.parent().unwrap(),
+                game.loader,
+                game.loader_version.clone(),
+            )
+            .context("Error while trying to create instance")?,
+        );
+        self.game = Some(game);
+        self.validate_java()
+    }
+}
+
+impl Variant {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn axes(&self) -> &BTreeMap<String, String> {
+        &self.axes
+    }
+
+    /// Substitutes variables in all string fields of the layers
+    fn substitute_variables(&mut self, vars: &BTreeMap<String, String>) -> Result<()> {
+        let instance = self.layers.iter().rev().find_map(|it| match it {
+            ResolvedLayer::Instance {
+                version,
+                loader,
+                loader_version,
+            } => Some((version, loader, loader_version)),
+            _ => None,
+        });
+        let lookup = |name: &str| -> Result<String> {
+            let instance = || instance.context(format!("`${{{name}}}` requires an instance layer"));
+            Ok(match name {
+                "game_version" => instance()?.0.clone(),
+                "loader" => instance()?.1.to_string().to_lowercase(),
+                "loader_version" => instance()?
+                    .2
+                    .clone()
+                    .context("The instance has no loader version")?,
+                "variant_name" => self.name.clone(),
+                name => match name.strip_prefix("env:") {
+                    Some(variable) => std::env::var(variable)
+                        .context(format!("Environment variable `{variable}` is not set"))?,
+                    None => vars
+                        .get(name)
+                        .cloned()
+                        .context(format!("Unknown variable `${{{name}}}`"))?,
+                },
+            })
+        };
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut value = serde_json::to_value(layer)?;
+                template::substitute_all(&mut value, &lookup)?;
+                Ok(serde_json::from_value(value)?)
+            })
+            .collect::<Result<_>>()?;
+        self.layers = layers;
+        Ok(())
+    }
+
+    /// Applies all layers of the variant inside `base_directory`, reusing versions `pinned` by a previous run.
+    /// Downloads and builds are cached in the cache directory of `context`
+    pub async fn setup(
+        self,
+        base_directory: PathBuf,
+        context: SetupContext,
+        pinned: Option<LockedVariant>,
+    ) -> Result<PreparedVariant> {
+        let mut state = SetupState {
+            instance: Either::Right(base_directory.join(&self.name)),
+            game: None,
+            launch_options: LaunchOptions::default(),
+            launch_config: instance::InstanceLaunchConfig::default(),
+            java: None,
+            pinned,
+            lock: LockedVariant::default(),
+            downloader: Downloader::new(context.cache_directory.clone())?,
+            context,
+        };
+        for resolved in self.layers {
+            resolved
+                .apply(&mut state)
+                .await
+                .context("Error while preparing profile")?;
+        }
+        let lock = LockedVariant {
+            game_version: state.game.as_ref().map(|it| it.version.clone()),
+            loader: state.game.as_ref().map(|it| it.loader.to_string()),
+            loader_version: state.game.as_ref().and_then(|it| it.loader_version.clone()),
+            ..state.lock
+        };
+        let report = VariantReport {
+            name: self.name,
+            game_version: state.game.as_ref().map(|it| it.version.clone()),
+            loader: state.game.as_ref().map(|it| it.loader.to_string()),
+            loader_version: state.game.as_ref().and_then(|it| it.loader_version.clone()),
+            java: state.java,
+            success: None,
+        };
+        Ok(PreparedVariant {
+            instance: state
+                .instance
+                .left()
+                .context("No instance was generated by profile")?,
+            launch_options: state.launch_options,
+            report,
+            lock,
+            game: state.game,
+        })
+    }
+}
+
+/// Removes `.` and `..` components without accessing the file system, as the directories may not exist yet
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// `target` joined onto `directory`, rejecting absolute targets and targets leaving the directory through `..`
+fn contained_path(directory: &Path, target: &Path) -> Result<PathBuf> {
+    let mut depth = 0;
+    for component in target.components() {
+        match component {
+            std::path::Component::Normal(_) => depth += 1,
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir if depth > 0 => depth -= 1,
+            _ => bail!(
+                "{} is not a path inside the variant directory",
+                target.display()
+            ),
+        }
+    }
+    Ok(directory.join(target))
+}
+
+fn range_key(versions: &str, snapshots: bool) -> String {
+    match snapshots {
+        true => format!("{versions} (with snapshots)"),
+        false => versions.to_owned(),
+    }
+}
+
+/// All combinations of axis values, without the excluded and with the included ones
+fn matrix_combinations(
+    axes: &BTreeMap<String, BTreeMap<String, Layer>>,
+    exclude: &[BTreeMap<String, String>],
+    include: &[BTreeMap<String, String>],
+) -> Vec<BTreeMap<String, String>> {
+    let mut combinations = vec![BTreeMap::new()];
+    for (axis, values) in axes {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination: BTreeMap<String, String>| {
+                values.keys().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(axis.clone(), value.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations.retain(|combination| {
+        !exclude.iter().any(|entry| {
+            entry
+                .iter()
+                .all(|(axis, value)| combination.get(axis) == Some(value))
+        })
+    });
+    for entry in include {
+        if !combinations.contains(entry) {
+            combinations.push(entry.clone());
+        }
+    }
+    combinations
+}
+
+/// Concatenates the layers or variants each entry stands for, failing with the first error
+fn flatten<T>(results: impl Iterator<Item = Result<Vec<T>>>) -> Result<Vec<T>> {
+    results
+        .collect::<Result<Vec<_>>>()
+        .map(|it| it.into_iter().flatten().collect())
+}
+
+fn loader_key(loader: instance::Modloader, game_version: &str, query: &str) -> String {
+    format!("{loader} {game_version} {query}")
+}
+
+/// Identifies a Modrinth mod layer in the lock, so changing what the layer asks for discards the pinned files
+fn mod_key(
+    project: &str,
+    version: Option<&str>,
+    version_range: Option<&str>,
+    resolve_dependencies: bool,
+) -> String {
+    let mut key = project.to_owned();
+    if let Some(version) = version {
+        key += &format!(" {version}");
+    }
+    if let Some(version_range) = version_range {
+        key += &format!(" ({version_range})");
+    }
+    if resolve_dependencies {
+        key += " (with dependencies)";
+    }
+    key
+}
+
+impl Profile {
+    /// Replaces layers depending on remote metadata, like version ranges, by the plain layers they stand for.
+    /// Has to be called before [`Self::get_variants`]
+    /// Versions already present in `lock` are reused, newly resolved ones are added to it.
+    pub async fn expand(&mut self, context: &SetupContext, lock: &mut ProfileLock) -> Result<()> {
+        self.check()?;
+        self.expand_ranges(context, lock).await?;
+        self.resolve_loader_versions(context, lock).await
+    }
+
+    /// Checks matrices and conditions, which can be done without resolving any versions
+    fn check(&self) -> Result<()> {
+        self.check_matrices()?;
+        let mut conditions = vec![];
+        self.visit_layers(&mut |it| {
+            if let Layer::When { condition, .. } = it {
+                conditions.push(condition.clone());
+            }
+        });
+        conditions.iter().try_for_each(Condition::validate)
+    }
+
+    /// Problems of the profile which would only show up while running it, like variants without an instance.
+    /// Version ranges are not resolved, so this works offline. `directory` is the directory the variants are set up in.
+    /// Variants without an instance are only reported if `require_instance` is set
+    pub fn problems(mut self, directory: &Path, require_instance: bool) -> Vec<String> {
+        if let Err(e) = self.check() {
+            return vec![format!("{e:#}")];
+        }
+        // Each range stands for a single instance, which is enough to check the structure of the profile
+        let _ = self.map_layers(&mut |layer| match layer {
+            Layer::InstanceRange {
+                versions,
+                loader,
+                loader_version,
+                ..
+            } => Ok(Layer::Instance {
+                version: versions,
+                loader,
+                loader_version,
+            }),
+            layer => Ok(layer),
+        });
+        let mut conditionals = vec![];
+        self.visit_layers(&mut |it| match it {
+            Layer::IfPresent { .. } | Layer::When { .. } => conditionals.push(it.clone()),
+            _ => {}
+        });
+        let variants = match self.clone().get_variants(self.name.clone() + "_") {
+            Result::Ok(variants) => variants,
+            Err(e) => return vec![format!("{e:#}")],
+        };
+
+        let mut problems = vec![];
+        let mut names = HashSet::new();
+        for variant in &variants {
+            if !names.insert(&variant.name) {
+                problems.push(format!("Multiple variants are named {}", variant.name));
+            }
+            let has_instance = variant.layers.iter().any(|it| {
+                matches!(
+                    it,
+                    ResolvedLayer::Instance { .. }
+                        | ResolvedLayer::CurseForgePack { .. }
+                        | ResolvedLayer::LocalPack { .. }
+                )
+            });
+            if require_instance && !has_instance {
+                problems.push(format!("Variant {} has no instance layer", variant.name));
+            }
+            for layer in &variant.layers {
+                if let ResolvedLayer::DirectoryOverlay { source } = layer {
+                    let source = normalize(&directory.join(&variant.name).join(source));
+                    if !source.is_dir() {
+                        problems.push(format!(
+                            "Overlay source {} of variant {} does not exist",
+                            source.display(),
+                            variant.name
+                        ));
+                    }
+                }
+            }
+        }
+        // Conditions are checked against all layers of a variant instead of only the preceding ones,
+        // so only conditionals which can't apply to any variant are reported
+        for conditional in conditionals {
+            let applies = |variant: &Variant| match &conditional {
+                Layer::IfPresent { check_for, .. } => variant.layers.contains(check_for),
+                Layer::When { condition, .. } => condition.matches(&variant.layers, &variant.axes),
+                _ => true,
+            };
+            let layer = serde_json::to_string(&conditional).unwrap_or_default();
+            if !variants.iter().any(&applies) {
+                problems.push(format!("Conditional layer never applies: {layer}"));
+            } else if matches!(
+                conditional,
+                Layer::When {
+                    otherwise: Some(_),
+                    ..
+                }
+            ) && variants.iter().all(&applies)
+            {
+                problems.push(format!(
+                    "Else branch of conditional layer never applies: {layer}"
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Rewrites all layers of the profile, including nested ones, using `f`
+    fn map_layers(&mut self, f: &mut impl FnMut(Layer) -> Result<Layer>) -> Result<()> {
+        self.layers = std::mem::take(&mut self.layers)
+            .into_iter()
+            .map(|it| it.map_nested(f))
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    fn visit_layers(&self, f: &mut impl FnMut(&Layer)) {
+        self.layers.iter().for_each(|it| it.visit(f));
+    }
+
+    /// Matrices are expanded while building the variants, so they can only appear directly in the
+    /// profile or inside other matrices, and their entries have to refer to existing axis values
+    fn check_matrices(&self) -> Result<()> {
+        fn check(layer: &Layer, nested: bool) -> Result<()> {
+            match layer {
+                Layer::Matrix {
+                    axes,
+                    exclude,
+                    include,
+                } => {
+                    ensure!(
+                        !nested,
+                        "Matrix layers can't be nested inside variants or conditional layers"
+                    );
+                    let exists = |axis: &String, value: &String| {
+                        axes.get(axis).is_some_and(|it| it.contains_key(value))
+                    };
+                    for (axis, value) in exclude.iter().flatten() {
+                        ensure!(
+                            exists(axis, value),
+                            "Matrix exclude entry refers to unknown value `{value}` of axis `{axis}`"
+                        );
+                    }
+                    for entry in include {
+                        ensure!(
+                            entry.len() == axes.len()
+                                && entry.iter().all(|(axis, value)| exists(axis, value)),
+                            "Matrix include entries have to choose an existing value for every axis"
+                        );
+                    }
+                    axes.values()
+                        .flat_map(|it| it.values())
+                        .try_for_each(|it| check(it, false))
+                }
+                Layer::Variants(variants) => variants.iter().try_for_each(|it| check(it, true)),
+                Layer::IfPresent { include, .. } | Layer::IfNotPresent { include, .. } => {
+                    check(include, true)
+                }
+                Layer::When {
+                    then, otherwise, ..
+                } => {
+                    check(then, true)?;
+                    otherwise.iter().try_for_each(|it| check(it, true))
+                }
+                _ => Ok(()),
+            }
+        }
+        self.layers.iter().try_for_each(|it| check(it, false))
+    }
+
+    async fn expand_ranges(
+        &mut self,
+        context: &SetupContext,
+        lock: &mut ProfileLock,
+    ) -> Result<()> {
+        let mut unlocked = false;
+        self.visit_layers(&mut |it| {
+            if let Layer::InstanceRange {
+                versions,
+                snapshots,
+                ..
+            } = it
+            {
+                unlocked |= !lock
+                    .version_ranges
+                    .contains_key(&range_key(versions, *snapshots));
+            }
+        });
+        let manifest = match unlocked {
+            true => Some(meta::VersionManifest::get(context).await?),
+            false => None,
+        };
+        self.map_layers(&mut |layer| match layer {
+            Layer::InstanceRange {
+                versions,
+                loader,
+                loader_version,
+                snapshots,
+            } => Ok(Layer::Variants(
+                match lock.version_ranges.get(&range_key(&versions, snapshots)) {
+                    Some(locked) => locked.clone(),
+                    None => {
+                        let selected = manifest
+                            .as_ref()
+                            .unwrap()
+                            .select(&versions, snapshots)
+                            .context(format!("Unable to expand version range `{versions}`"))?;
+                        lock.version_ranges
+                            .insert(range_key(&versions, snapshots), selected.clone());
+                        selected
+                    }
+                }
+                .into_iter()
+                .map(|version| Layer::Instance {
+                    version,
+                    loader,
+                    loader_version: loader_version.clone(),
+                })
+                .collect(),
+            )),
+            layer => Ok(layer),
+        })
+    }
+
+    /// Resolves loader versions like `latest`, `latest-stable` or `>=0.14` to concrete versions
+    async fn resolve_loader_versions(
+        &mut self,
+        context: &SetupContext,
+        lock: &mut ProfileLock,
+    ) -> Result<()> {
+        let mut queries = vec![];
+        self.visit_layers(&mut |it| {
+            if let Layer::Instance {
+                version,
+                loader,
+                loader_version: Some(loader_version),
+            } = it
+            {
+                let query = (*loader, version.clone(), loader_version.clone());
+                if meta::is_loader_query(loader_version)
+                    && !queries.contains(&query)
+                    && !lock.loader_versions.contains_key(&loader_key(
+                        *loader,
+                        version,
+                        loader_version,
+                    ))
+                {
+                    queries.push(query);
+                }
+            }
+        });
+        for (loader, version, query) in queries {
+            ensure!(
+                !context.offline,
+                "{loader} version `{query}` for minecraft {version} can't be resolved offline"
+            );
+            let loader_version =
+                meta::resolve_loader_version(context, loader, &version, &query).await?;
+            lock.loader_versions
+                .insert(loader_key(loader, &version, &query), loader_version);
+        }
+        self.map_layers(&mut |layer| match layer {
+            Layer::Instance {
+                version,
+                loader,
+                loader_version: Some(loader_version),
+            } => {
+                let loader_version = lock
+                    .loader_versions
+                    .get(&loader_key(loader, &version, &loader_version))
+                    .cloned()
+                    .unwrap_or(loader_version);
+                Ok(Layer::Instance {
+                    version,
+                    loader,
+                    loader_version: Some(loader_version),
+                })
+            }
+            layer => Ok(layer),
+        })
+    }
+
+    /// The variants of the profile with variables substituted, which fails for unknown variables
+    pub fn get_variants(self, name: String) -> Result<Vec<Variant>> {
+        let vars = self.vars;
+        Self::get_variants_rec(&[], &BTreeMap::new(), &mut self.layers.into(), name)?
+            .into_iter()
+            .map(|mut it| {
+                it.substitute_variables(&vars).context(format!(
+                    "Unable to substitute variables in variant {}",
+                    it.name
+                ))?;
+                Ok(it)
+            })
+            .collect()
+    }
+
+    fn get_variants_rec(
+        prev: &[ResolvedLayer],
+        axes: &BTreeMap<String, String>,
+        coming: &mut VecDeque<Layer>,
+        name: String,
+    ) -> Result<Vec<Variant>> {
+        let Some(layer) = coming.pop_front() else {
+            return Ok(vec![Variant {
+                layers: prev.to_vec(),
+                name,
+                axes: axes.clone(),
+            }]);
+        };
+
+        if let Layer::Matrix {
+            axes: matrix,
+            exclude,
+            include,
+        } = layer
+        {
+            // The layers of the chosen values are applied in place of the matrix
+            return flatten(
+                matrix_combinations(&matrix, &exclude, &include)
+                    .into_iter()
+                    .map(|combination| {
+                        let mut coming = coming.clone();
+                        for (axis, value) in combination.iter().rev() {
+                            coming.push_front(matrix[axis][value].clone());
+                        }
+                        let mut axes = axes.clone();
+                        axes.extend(combination.clone());
+                        let separator = if name.ends_with('_') { "" } else { "_" };
+                        let values: Vec<String> = combination.into_values().collect();
+                        Self::get_variants_rec(
+                            prev,
+                            &axes,
+                            &mut coming,
+                            format!("{name}{separator}{}_", values.join("-")),
+                        )
+                    }),
+            );
+        }
+
+        let resolved = layer.resolve(prev, axes)?;
+        if resolved.is_empty() {
+            return Self::get_variants_rec(prev, axes, coming, name);
+        }
+
+        flatten(
+            resolved
+                .into_iter()
+                .enumerate()
+                .map(|(index, resolved_layer)| {
+                    Self::get_variants_rec(
+                        &[prev, &[resolved_layer]].concat(),
+                        axes,
+                        &mut coming.clone(),
+                        format!("{name}{index:02}"),
+                    )
+                }),
+        )
+    }
+}
+
+impl ResolvedLayer {
+    /// The type of the layer as written in the config, e.g. `modrinth_mod`
+    pub fn kind(&self) -> String {
+        match serde_json::to_value(self).unwrap_or_default() {
+            serde_json::Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+            serde_json::Value::String(kind) => kind,
+            _ => String::new(),
+        }
+    }
+
+    pub async fn apply(&self, state: &mut SetupState) -> Result<()> {
+        let path = state.path().clone();
+        match self {
+            Self::DeleteDirectory(target) => {
+                let full_target_path = path.join(target);
+                if full_target_path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                }
+            }
+            Self::Instance {
+                version,
+                loader,
+                loader_version,
+            } => state.create_instance(GameInfo {
+                version: version.clone(),
+                loader: *loader,
+                loader_version: loader_version.clone(),
+            })?,
+            Self::DirectoryOverlay { source } => {
+                copy_dir_all(path.join(source), &path)?;
+            }
+            Self::ExecuteCommand(cmd) => {
+                if !run_cmd(cmd, &path)?.status.success() {
+                    bail!("Command `{cmd}` didnt ran propely");
+                }
+            }
+            Self::ModrinthPack { id: _, version: _ } => {
+                todo!("Modpack support")
+            }
+            Self::ModrinthMod {
+                project,
+                version,
+                version_range,
+                resolve_dependencies,
+            } => {
+                let mods = path.join(GAME_DIR).join("mods");
+                let key = mod_key(
+                    project,
+                    version.as_deref(),
+                    version_range.as_deref(),
+                    *resolve_dependencies,
+                );
+                let files = match state.pinned_files(&key) {
+                    Some(files) => {
+                        modrinth::install_locked(&state.downloader, files, &mods)
+                            .await
+                            .context(format!("Unable to install locked {project}"))?;
+                        files.clone()
+                    }
+                    None => {
+                        let game = state.game()?;
+                        let version_range = version_range
+                            .as_deref()
+                            .map(VersionReq::parse)
+                            .transpose()?;
+                        let version = modrinth::find_version(
+                            &state.downloader,
+                            project,
+                            version.as_deref(),
+                            version_range.as_ref(),
+                            game,
+                        )
+                        .await?;
+                        let installed = modrinth::install(
+                            &state.downloader,
+                            version,
+                            *resolve_dependencies,
+                            game,
+                            &mods,
+                        )
+                        .await
+                        .context(format!("Unable to install {project}"))?;
+                        modrinth::lock(&installed)?
+                    }
+                };
+                state.lock.mods.insert(key, files);
+            }
+            Self::CurseForgePack { id, file } => {
+                let client = curseforge::Client::new(state.downloader.clone())?;
+                // The pack decides the game version, so its pin is kept whatever the previous run used
+                let key = format!("pack {id}");
+                let pinned = state.pinned.as_ref().and_then(|it| it.curseforge.get(&key));
+                let locked = match (file, pinned) {
+                    (Some(file), _) => client.file(*id, *file).await?.lock()?,
+                    (None, Some(pinned)) => pinned.clone(),
+                    (None, None) => client.latest_file(*id, None).await?.lock()?,
+                };
+                let pack_directory = path.join(".curseforge_pack");
+                state
+                    .downloader
+                    .download_locked(&locked, &pack_directory)
+                    .await?;
+                let pack = pack_directory.join(&locked.filename);
+                let manifest: curseforge::Manifest =
+                    archive::read_json(&mut archive::open(&pack)?, "manifest.json")?;
+                state.create_instance(manifest.game()?)?;
+                client
+                    .install_pack(&manifest, &pack, &path.join(GAME_DIR))
+                    .await
+                    .context(format!("Unable to install CurseForge pack {id}"))?;
+                fs::remove_dir_all(pack_directory)?;
+                if file.is_none() {
+                    state.lock.curseforge.insert(key, locked);
+                }
+            }
+            Self::CurseForgeMod { id, file } => {
+                let key = format!("mod {id}");
+                let pinned = state
+                    .pinned_for_game()
+                    .and_then(|it| it.curseforge.get(&key));
+                // Pinned files are downloaded without the api, so they don't need an api key
+                let locked = match (file, pinned) {
+                    (Some(file), _) => curseforge::Client::new(state.downloader.clone())?
+                        .file(*id, *file)
+                        .await?
+                        .lock()?,
+                    (None, Some(pinned)) => pinned.clone(),
+                    (None, None) => curseforge::Client::new(state.downloader.clone())?
+                        .latest_file(*id, Some(state.game()?))
+                        .await?
+                        .lock()?,
+                };
+                state
+                    .downloader
+                    .download_locked(&locked, &path.join(GAME_DIR).join("mods"))
+                    .await
+                    .context(format!("Unable to install CurseForge project {id}"))?;
+                if file.is_none() {
+                    state.lock.curseforge.insert(key, locked);
+                }
+            }
+            Self::LocalPack { path: pack } => {
+                let pack = path.join(pack);
+                let mut archive = archive::open(&pack)?;
+                let installed = if archive::contains(&archive, mrpack::INDEX_FILE) {
+                    let index: mrpack::Index =
+                        archive::read_json(&mut archive, mrpack::INDEX_FILE)?;
+                    state.create_instance(index.game()?)?;
+                    index
+                        .install(&state.downloader, &pack, &path.join(GAME_DIR))
+                        .await
+                } else {
+                    let manifest: curseforge::Manifest =
+                        archive::read_json(&mut archive, "manifest.json")?;
+                    state.create_instance(manifest.game()?)?;
+                    curseforge::Client::new(state.downloader.clone())?
+                        .install_pack(&manifest, &pack, &path.join(GAME_DIR))
+                        .await
+                };
+                installed.context(format!("Unable to install modpack {}", pack.display()))?;
+            }
+            Self::Download { url, target, hash } => {
+                state
+                    .downloader
+                    .download(url, &contained_path(&path, target)?, Some(hash))
+                    .await
+                    .context(format!("Unable to download {url}"))?;
+            }
+            Self::MavenArtifact {
+                repo,
+                coordinates,
+                target,
+            } => {
+                let directory = match target {
+                    Some(target) => contained_path(&path, target)?,
+                    None => path.join(GAME_DIR).join("mods"),
+                };
+                let parsed = maven::Coordinates::parse(coordinates)?;
+                // Fixed versions don't change, only `latest` and snapshots are pinned
+                let key = format!("{repo} {coordinates}");
+                let pinned = state
+                    .pinned
+                    .as_ref()
+                    .and_then(|it| it.maven.get(&key))
+                    .filter(|_| parsed.is_dynamic());
+                let locked = match pinned {
+                    Some(pinned) => state
+                        .downloader
+                        .download_locked(pinned, &directory)
+                        .await
+                        .map(|_| pinned.clone()),
+                    None => maven::install(&state.downloader, repo, &parsed, &directory).await,
+                }
+                .context(format!("Unable to install {coordinates} from {repo}"))?;
+                if parsed.is_dynamic() {
+                    state.lock.maven.insert(key, locked);
+                }
+            }
+            Self::GradleBuild {
+                project_dir,
+                task,
+                artifact_glob,
+            } => {
+                let project_dir = path.join(project_dir);
+                fs::create_dir_all(&path)?;
+                let artifact = gradle::Build {
+                    project_dir: &project_dir,
+                    task: task.as_deref().unwrap_or("build"),
+                    artifact_glob: artifact_glob.as_deref().unwrap_or("build/libs/*.jar"),
+                    offline: state.context.offline,
+                }
+                .run(&state.context.cache_directory, &path.join(SETUP_LOG))
+                .await
+                .context(format!("Unable to build {}", project_dir.display()))?;
+                let mods = path.join(GAME_DIR).join("mods");
+                fs::create_dir_all(&mods)?;
+                fs::copy(&artifact, mods.join(artifact.file_name().unwrap()))?;
+            }
+            Self::WriteFile { target, content } => {
+                patch::write(&contained_path(&path, target)?, content)?
+            }
+            Self::PatchProperties {
+                target,
+                set,
+                remove,
+            } => patch::patch_properties(&contained_path(&path, target)?, set, remove)?,
+            Self::PatchConfig {
+                target,
+                set,
+                remove,
+            } => patch::patch_config(&contained_path(&path, target)?, set, remove)?,
+            Self::LaunchConfig(launch_config) => {
+                launch_config.merge_into(&mut state.launch_config);
+                state.update_launch_config();
+            }
+            Self::Java {
+                version,
+                path: java_path,
+                archive,
+            } => {
+                let cache_directory = &state.context.cache_directory;
+                let java = match (java_path, archive, version) {
+                    (Some(java_path), _, _) => JavaRuntime::probe(&path.join(java_path))?,
+                    (None, Some(archive), _) => {
+                        JavaRuntime::provision(&path.join(archive), cache_directory)?
+                    }
+                    (None, None, Some(version)) => JavaRuntime::find(*version, cache_directory)?,
+                    (None, None, None) => {
+                        bail!("The java layer requires a version, path or archive")
+                    }
+                };
+                if let Some(version) = version {
+                    ensure!(
+                        java.major == *version,
+                        "Java {version} was requested, but {} is java {}",
+                        java.path.display(),
+                        java.major
+                    );
+                }
+                state.launch_config.custom_java = Some(java.path.to_string_lossy().to_string());
+                state.update_launch_config();
+                state.java = Some(java);
+                state.validate_java()?;
+            }
+            Self::LaunchClient(launch_options) => {
+                state.launch_options = launch_options.clone();
+            }
+        };
+        return Ok(());
+
+        fn run_cmd(cmd: &String, path: &PathBuf) -> Result<Output> {
+            if cfg!(target_os = "windows") {
+                Command::new("cmd")
+                    .current_dir(path)
+                    .args(["/C", cmd])
+                    .output()
+                    .context("failed to execute process")
+            } else {
+                Command::new("sh")
+                    .current_dir(path)
+                    .arg("-c")
+                    .arg(cmd)
+                    .output()
+                    .context("failed to execute process")
+            }
+        }
+        fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
+            fs::create_dir_all(&dst)?;
+            for entry in fs::read_dir(src)? {
+                let entry = entry?;
+                let ty = entry.file_type()?;
+                if ty.is_dir() {
+                    copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+                } else {
+                    fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        fs,
+        path::Path,
+    };
+
+    use serde_json::json;
+    use sha1::Digest;
+
+    use either::Either;
+    use helixlauncher_core::launch::instance;
+
+    use super::{
+        contained_path,
+        download::Downloader,
+        matrix_combinations, mod_key,
+        test_util::{temp_dir, TestServer},
+        GameInfo, LaunchConfig, LaunchOptions, Layer, Profile, ResolvedLayer, SetupContext,
+        SetupState, GAME_DIR,
+    };
+    use crate::lock::{LockedFile, LockedVariant, ProfileLock};
+
+    fn profile(layers: serde_json::Value) -> Profile {
+        let mut profile: Profile = serde_json::from_value(json!({ "layers": layers })).unwrap();
+        profile.name = "test".to_owned();
+        profile
+    }
+
+    fn instance_versions(profile: Profile) -> Vec<(String, String)> {
+        profile
+            .get_variants("test_".to_owned())
+            .unwrap()
+            .into_iter()
+            .map(|variant| {
+                let version = variant.layers.iter().find_map(|it| match it {
+                    ResolvedLayer::Instance { version, .. } => Some(version.clone()),
+                    _ => None,
+                });
+                (variant.name, version.unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn contains_paths_in_directory() {
+        let directory = Path::new("/profiles/a/a_1");
+        for target in [
+            ".minecraft/mods/a.jar",
+            "./options.txt",
+            "config/../options.txt",
+        ] {
+            assert_eq!(
+                contained_path(directory, Path::new(target)).unwrap(),
+                directory.join(target)
+            );
+        }
+        for target in [
+            "/etc/passwd",
+            "../a_2/options.txt",
+            "config/../../a.jar",
+            "..",
+        ] {
+            assert!(
+                contained_path(directory, Path::new(target)).is_err(),
+                "{target}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        let variants = |command: &str| {
+            let mut profile = profile(json!([
+                { "instance": { "version": "1.20.1", "loader": "vanilla" } },
+                { "execute_command": command },
+            ]));
+            profile.vars.insert("name".to_owned(), "world".to_owned());
+            profile.get_variants("test_".to_owned())
+        };
+        let variant = &variants("echo ${name} ${game_version} $${HOME}").unwrap()[0];
+        assert_eq!(
+            variant.layers[1],
+            ResolvedLayer::ExecuteCommand("echo world 1.20.1 ${HOME}".to_owned())
+        );
+        let error = variants("echo ${game_verison}").unwrap_err();
+        assert!(
+            format!("{error:#}").contains("Unknown variable `${game_verison}`"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn merges_launch_configs() {
+        let layers: Vec<LaunchConfig> = serde_json::from_value(json!([
+            {
+                "jvm_args": ["-XX:+UseG1GC"],
+                "system_properties": { "b.debug": "true", "a.level": "1" },
+                "min_memory": "512M",
+                "max_memory": "2G",
+                "env": { "A": "1", "B": "1" },
+            },
+            { "max_memory": "4G", "env": { "B": "2", "C": "2" } },
+            { "system_properties": { "c.debug": "false" }, "min_memory": "1G" },
+        ]))
+        .unwrap();
+        let mut config = instance::InstanceLaunchConfig::default();
+        config.jvm_args.push("-Xmx1G".to_owned());
+        for layer in &layers {
+            layer.merge_into(&mut config);
+        }
+        assert_eq!(
+            config.jvm_args,
+            [
+                "-Da.level=1",
+                "-Db.debug=true",
+                "-XX:+UseG1GC",
+                "-Xmx4G",
+                "-Xms1G",
+                "-Dc.debug=false",
+            ]
+        );
+        let mut env: Vec<(&str, &str)> = config
+            .env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        env.sort();
+        assert_eq!(env, [("A", "1"), ("B", "2"), ("C", "2")]);
+    }
+
+    #[tokio::test]
+    async fn expands_ranges_from_lock() {
+        let mut profile = profile(json!([{ "variants": [
+            { "instance_range": { "versions": "1.20.x", "loader": "fabric", "loader_version": "0.14.21" } },
+            { "instance_range": { "versions": "1.20.x", "loader": "vanilla", "snapshots": true } },
+        ] }]));
+        assert!(profile.clone().get_variants("test_".to_owned()).is_err());
+
+        let mut lock = ProfileLock::default();
+        lock.version_ranges.insert(
+            "1.20.x".to_owned(),
+            vec!["1.20".to_owned(), "1.20.1".to_owned()],
+        );
+        lock.version_ranges.insert(
+            "1.20.x (with snapshots)".to_owned(),
+            vec!["1.20.1-rc1".to_owned()],
+        );
+        let context = SetupContext {
+            cache_directory: temp_dir("ranges"),
+            offline: true,
+        };
+        profile.expand(&context, &mut lock).await.unwrap();
+        assert_eq!(
+            instance_versions(profile),
+            [
+                ("test_00".to_owned(), "1.20".to_owned()),
+                ("test_01".to_owned(), "1.20.1".to_owned()),
+                ("test_02".to_owned(), "1.20.1-rc1".to_owned()),
+            ]
+        );
+    }
+
+    /// The lock of a previous run for minecraft 1.20.1 with fabric
+    fn pinned_variant() -> LockedVariant {
+        LockedVariant {
+            game_version: Some("1.20.1".to_owned()),
+            loader: Some(instance::Modloader::Fabric.to_string()),
+            loader_version: Some("0.14.21".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn setup_state(directory: &Path, pinned: LockedVariant) -> SetupState {
+        SetupState {
+            instance: Either::Right(directory.to_path_buf()),
+            game: None,
+            launch_options: LaunchOptions::default(),
+            launch_config: instance::InstanceLaunchConfig::default(),
+            java: None,
+            pinned: Some(pinned),
+            lock: LockedVariant::default(),
+            downloader: Downloader::new(directory.join("cache")).unwrap(),
+            context: SetupContext {
+                cache_directory: directory.join("cache"),
+                offline: true,
+            },
+        }
+    }
+
+    #[test]
+    fn reuses_pins_only_for_same_game() {
+        let key = mod_key("sodium", None, Some(">=0.5"), true);
+        assert_eq!(key, "sodium (>=0.5) (with dependencies)");
+        assert_ne!(key, mod_key("sodium", None, Some(">=0.4"), true));
+        assert_ne!(key, mod_key("sodium", None, Some(">=0.5"), false));
+        assert_eq!(
+            mod_key("sodium", Some("mc1.20-0.5.0"), None, false),
+            "sodium mc1.20-0.5.0"
+        );
+
+        let file = LockedFile {
+            project_id: "AANobbMI".to_owned(),
+            version_id: "OihdIimA".to_owned(),
+            filename: "sodium.jar".to_owned(),
+            url: "https://cdn.modrinth.com/sodium.jar".to_owned(),
+            sha1: "da39a3ee5e6b4b0d3255bfef95601890afd80709".to_owned(),
+        };
+        let mut state = setup_state(
+            &temp_dir("pins"),
+            LockedVariant {
+                mods: [(key.clone(), vec![file])].into(),
+                ..pinned_variant()
+            },
+        );
+        assert!(state.pinned_files(&key).is_none());
+        for (version, loader, pinned) in [
+            ("1.20.1", instance::Modloader::Fabric, true),
+            ("1.20", instance::Modloader::Fabric, false),
+            ("1.20.1", instance::Modloader::Quilt, false),
+        ] {
+            state.game = Some(GameInfo {
+                version: version.to_owned(),
+                loader,
+                loader_version: None,
+            });
+            assert_eq!(
+                state.pinned_files(&key).is_some(),
+                pinned,
+                "{version} {loader}"
+            );
+        }
+        assert!(state.pinned_files("sodium").is_none());
+    }
+
+    #[tokio::test]
+    async fn reuses_pinned_curseforge_and_maven_files() {
+        let server = TestServer::start(|_| {
+            [
+                ("/pinned/cf.jar", "pinned curseforge"),
+                ("/pinned/lib.jar", "pinned maven"),
+                ("/maven/dev/test/fixed/1.0/fixed-1.0.jar", "fixed maven"),
+            ]
+            .into_iter()
+            .map(|(path, body)| (path.to_owned(), body.as_bytes().to_vec()))
+            .collect::<HashMap<_, _>>()
+        })
+        .await;
+        let locked_file = |name: &str, content: &str| LockedFile {
+            project_id: name.to_owned(),
+            version_id: name.to_owned(),
+            filename: format!("{name}.jar"),
+            url: format!("{}/pinned/{name}.jar", server.url),
+            sha1: hex::encode(sha1::Sha1::digest(content)),
+        };
+        let repo = format!("{}/maven", server.url);
+        let maven_key = format!("{repo} dev.test:lib:latest");
+        let directory = temp_dir("pinned-files");
+        let mut state = setup_state(
+            &directory,
+            LockedVariant {
+                curseforge: [("mod 5".to_owned(), locked_file("cf", "pinned curseforge"))].into(),
+                maven: [(maven_key.clone(), locked_file("lib", "pinned maven"))].into(),
+                ..pinned_variant()
+            },
+        );
+        state.game = Some(GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Fabric,
+            loader_version: None,
+        });
+
+        // Resolving any of these would fail, as the server has neither the api nor maven metadata
+        for layer in [
+            ResolvedLayer::CurseForgeMod { id: 5, file: None },
+            ResolvedLayer::MavenArtifact {
+                repo: repo.clone(),
+                coordinates: "dev.test:lib:latest".to_owned(),
+                target: None,
+            },
+            ResolvedLayer::MavenArtifact {
+                repo: repo.clone(),
+                coordinates: "dev.test:fixed:1.0".to_owned(),
+                target: None,
+            },
+        ] {
+            layer.apply(&mut state).await.unwrap();
+        }
+        let mods = directory.join(GAME_DIR).join("mods");
+        for (name, content) in [
+            ("cf.jar", "pinned curseforge"),
+            ("lib.jar", "pinned maven"),
+            ("fixed-1.0.jar", "fixed maven"),
+        ] {
+            assert_eq!(fs::read_to_string(mods.join(name)).unwrap(), content);
+        }
+        assert_eq!(state.lock.curseforge["mod 5"].filename, "cf.jar");
+        assert_eq!(state.lock.maven.keys().collect::<Vec<_>>(), [&maven_key]);
+        assert_eq!(state.lock.maven[&maven_key].filename, "lib.jar");
+    }
+
+    fn combination(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(axis, value)| (axis.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn combines_matrix_axes() {
+        let values = |names: &[&str]| -> BTreeMap<String, Layer> {
+            names
+                .iter()
+                .map(|it| (it.to_string(), Layer::ExecuteCommand(it.to_string())))
+                .collect()
+        };
+        let axes = BTreeMap::from([
+            ("loader".to_owned(), values(&["fabric", "forge"])),
+            ("version".to_owned(), values(&["1.14", "1.20"])),
+        ]);
+        assert_eq!(
+            matrix_combinations(&axes, &[], &[]),
+            [
+                combination(&[("loader", "fabric"), ("version", "1.14")]),
+                combination(&[("loader", "fabric"), ("version", "1.20")]),
+                combination(&[("loader", "forge"), ("version", "1.14")]),
+                combination(&[("loader", "forge"), ("version", "1.20")]),
+            ]
+        );
+        // Partial exclude entries drop every combination they match, included ones are only added once
+        assert_eq!(
+            matrix_combinations(
+                &axes,
+                &[
+                    combination(&[("loader", "forge"), ("version", "1.14")]),
+                    combination(&[("version", "1.20")]),
+                ],
+                &[
+                    combination(&[("loader", "forge"), ("version", "1.20")]),
+                    combination(&[("loader", "fabric"), ("version", "1.14")]),
+                ],
+            ),
+            [
+                combination(&[("loader", "fabric"), ("version", "1.14")]),
+                combination(&[("loader", "forge"), ("version", "1.20")]),
+            ]
+        );
+        assert_eq!(
+            matrix_combinations(&BTreeMap::new(), &[], &[]),
+            [BTreeMap::new()]
+        );
+    }
+
+    #[test]
+    fn names_matrix_variants() {
+        let matrix = profile(json!([
+            { "matrix": {
+                "axes": {
+                    "loader": {
+                        "fabric": { "execute_command": "fabric" },
+                        "quilt": { "execute_command": "quilt" },
+                    },
+                    "version": { "1.20": { "execute_command": "1.20" } },
+                },
+                "exclude": [{ "loader": "quilt" }],
+            } },
+            { "variants": [{ "execute_command": "a" }, { "execute_command": "b" }] },
+        ]));
+        matrix.check().unwrap();
+        let variants = matrix.get_variants("test_".to_owned()).unwrap();
+        let names: Vec<&str> = variants.iter().map(|it| it.name()).collect();
+        assert_eq!(
+            names,
+            ["test_fabric-1.20_000000", "test_fabric-1.20_000001"]
+        );
+        assert_eq!(
+            variants[1].axes(),
+            &combination(&[("loader", "fabric"), ("version", "1.20")])
+        );
+        assert_eq!(
+            variants[1].layers,
+            ["fabric", "1.20", "b"].map(|it| ResolvedLayer::ExecuteCommand(it.to_owned()))
+        );
+
+        let nested = profile(json!([{ "variants": [
+            { "matrix": { "axes": { "a": { "b": { "execute_command": "c" } } } } },
+        ] }]));
+        assert!(nested.check().is_err());
+        assert!(nested.get_variants("test_".to_owned()).is_err());
+    }
+
+    #[test]
+    fn reports_problems() {
+        let directory = temp_dir("problems");
+        let base = profile(json!([
+            { "directory_overlay": { "source": "missing" } },
+            { "when": {
+                "condition": { "loader": "forge" },
+                "then": { "execute_command": "forge" },
+            } },
+        ]));
+        assert_eq!(
+            base.clone().problems(&directory, true),
+            [
+                "Variant test_00 has no instance layer".to_owned(),
+                format!(
+                    "Overlay source {} of variant test_00 does not exist",
+                    directory.join("test_00").join("missing").display()
+                ),
+                r#"Conditional layer never applies: {"when":{"condition":{"loader":"forge"},"then":{"execute_command":"forge"}}}"#.to_owned(),
+            ]
+        );
+        assert_eq!(base.problems(&directory, false).len(), 2);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    env,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use helixlauncher_core::launch::instance;
+use serde::Deserialize;
+
+use super::{
+    download::{Downloader, Hash},
+    GameInfo,
+};
+use crate::{lock::LockedFile, version::VersionReq};
+
+const DEFAULT_API_URL: &str = "https://api.modrinth.com/v2";
+
+/// The Modrinth api base url, can be overridden using `MODRINTH_API_URL` e.g. to test against a mock server
+fn api_url() -> String {
+    env::var("MODRINTH_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_owned())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    pub version_number: String,
+    pub date_published: String,
+    pub files: Vec<VersionFile>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VersionFile {
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub primary: bool,
+    pub hashes: FileHashes,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FileHashes {
+    pub sha1: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Dependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    pub dependency_type: DependencyType,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyType {
+    Required,
+    Optional,
+    Incompatible,
+    Embedded,
+}
+
+impl Version {
+    pub fn primary_file(&self) -> Result<&VersionFile> {
+        self.files
+            .iter()
+            .find(|it| it.primary)
+            .or(self.files.first())
+            .context(format!("Version {} has no files", self.id))
+    }
+}
+
+fn loaders(loader: instance::Modloader) -> Result<&'static [&'static str]> {
+    Ok(match loader {
+        instance::Modloader::Fabric => &["fabric"],
+        instance::Modloader::Quilt => &["quilt", "fabric"],
+        instance::Modloader::Forge => &["forge"],
+        instance::Modloader::Vanilla => bail!("Mods can't be installed without a mod loader"),
+    })
+}
+
+/// Finds the newest version of `project` compatible with the game version and loader of `game`
+pub async fn find_version(
+    downloader: &Downloader,
+    project: &str,
+    version: Option<&str>,
+    version_range: Option<&VersionReq>,
+    game: &GameInfo,
+) -> Result<Version> {
+    let versions: Vec<Version> = downloader
+        .client
+        .get(format!("{}/project/{project}/version", api_url()))
+        .query(&[
+            ("loaders", serde_json::to_string(loaders(game.loader)?)?),
+            ("game_versions", serde_json::to_string(&[&game.version])?),
+        ])
+        .send()
+        .await
+        .and_then(|it| it.error_for_status())
+        .context(format!("Unable to fetch versions of {project}"))?
+        .json()
+        .await
+        .context(format!("Invalid version list for {project}"))?;
+    versions
+        .into_iter()
+        .filter(|it| match version {
+            Some(version) => it.id == version || it.version_number == version,
+            None => true,
+        })
+        .filter(|it| match version_range {
+            Some(range) => range.matches(&it.version_number),
+            None => true,
+        })
+        .max_by(|a, b| a.date_published.cmp(&b.date_published))
+        .context(format!(
+            "No version of {project} compatible with Minecraft {} ({}) was found",
+            game.version, game.loader
+        ))
+}
+
+async fn get_version(downloader: &Downloader, id: &str) -> Result<Version> {
+    downloader
+        .client
+        .get(format!("{}/version/{id}", api_url()))
+        .send()
+        .await
+        .and_then(|it| it.error_for_status())
+        .context(format!("Unable to fetch version {id}"))?
+        .json()
+        .await
+        .context(format!("Invalid version {id}"))
+}
+
+/// Downloads the primary file of `version` into `mods_dir`, optionally followed by all of its required dependencies
+pub async fn install(
+    downloader: &Downloader,
+    version: Version,
+    resolve_dependencies: bool,
+    game: &GameInfo,
+    mods_dir: &Path,
+) -> Result<Vec<Version>> {
+    let mut installed = vec![];
+    let mut seen = HashSet::from([version.project_id.clone()]);
+    let mut queue = VecDeque::from([version]);
+    while let Some(version) = queue.pop_front() {
+        let file = version.primary_file()?;
+        downloader
+            .download(
+                &file.url,
+                &mods_dir.join(&file.filename),
+                Some(&Hash::Sha1(file.hashes.sha1.clone())),
+            )
+            .await?;
+        if resolve_dependencies {
+            for dependency in &version.dependencies {
+                if dependency.dependency_type != DependencyType::Required {
+                    continue;
+                }
+                let dependency = match (&dependency.version_id, &dependency.project_id) {
+                    (Some(version_id), _) => get_version(downloader, version_id).await?,
+                    (None, Some(project_id)) => {
+                        if seen.contains(project_id) {
+                            continue;
+                        }
+                        find_version(downloader, project_id, None, None, game)
+                            .await
+                            .context(format!(
+                                "Unable to resolve dependency of {}",
+                                version.project_id
+                            ))?
+                    }
+                    (None, None) => continue,
+                };
+                if seen.insert(dependency.project_id.clone()) {
+                    queue.push_back(dependency);
+                }
+            }
+        }
+        installed.push(version);
+    }
+    Ok(installed)
+}
+
+/// The primary files of `versions` in the form stored in the lock file
+pub fn lock(versions: &[Version]) -> Result<Vec<LockedFile>> {
+    versions
+        .iter()
+        .map(|version| {
+            let file = version.primary_file()?;
+            Ok(LockedFile {
+                project_id: version.project_id.clone(),
+                version_id: version.id.clone(),
+                filename: file.filename.clone(),
+                url: file.url.clone(),
+                sha1: file.hashes.sha1.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Downloads previously locked files into `mods_dir` without querying the api
+pub async fn install_locked(
+    downloader: &Downloader,
+    files: &[LockedFile],
+    mods_dir: &Path,
+) -> Result<()> {
+    for file in files {
+        downloader.download_locked(file, mods_dir).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs};
+
+    use helixlauncher_core::launch::instance;
+    use serde_json::json;
+    use sha1::Digest;
+
+    use super::{find_version, install, lock};
+    use crate::{
+        layer::{
+            download::Downloader,
+            test_util::{temp_dir, TestServer},
+            GameInfo,
+        },
+        version::VersionReq,
+    };
+
+    fn version(
+        url: &str,
+        project: &str,
+        number: &str,
+        date: &str,
+        dependencies: serde_json::Value,
+    ) -> serde_json::Value {
+        let filename = format!("{project}-{number}.jar");
+        json!({
+            "id": format!("{project}-{number}"),
+            "project_id": project,
+            "version_number": number,
+            "date_published": date,
+            "files": [{
+                "url": format!("{url}/files/{filename}"),
+                "filename": filename,
+                "primary": true,
+                "hashes": { "sha1": hex::encode(sha1::Sha1::digest(filename.as_bytes())) },
+            }],
+            "dependencies": dependencies,
+        })
+    }
+
+    /// A mock of the Modrinth api. sodium 0.4.10 requires indium, which requires a fixed fabric-api version.
+    /// Each file contains its own name
+    async fn mock_api() -> TestServer {
+        TestServer::start(|url| {
+            let versions = [
+                (
+                    "/project/sodium/version",
+                    json!([
+                        version(
+                            url,
+                            "sodium",
+                            "0.4.10",
+                            "2023-06-01T00:00:00Z",
+                            json!([
+                                { "project_id": "indium", "dependency_type": "required" },
+                                { "project_id": "iris", "dependency_type": "optional" },
+                            ])
+                        ),
+                        version(url, "sodium", "0.5.0", "2023-07-01T00:00:00Z", json!([])),
+                    ]),
+                ),
+                (
+                    "/project/indium/version",
+                    json!([version(
+                        url,
+                        "indium",
+                        "1.0.21",
+                        "2023-06-02T00:00:00Z",
+                        json!([
+                            { "version_id": "fabric-api-0.84.0", "dependency_type": "required" },
+                            { "project_id": "sodium", "dependency_type": "required" },
+                        ])
+                    )]),
+                ),
+                (
+                    "/version/fabric-api-0.84.0",
+                    version(
+                        url,
+                        "fabric-api",
+                        "0.84.0",
+                        "2023-06-03T00:00:00Z",
+                        json!([]),
+                    ),
+                ),
+            ];
+            let files = [
+                "sodium-0.4.10",
+                "sodium-0.5.0",
+                "indium-1.0.21",
+                "fabric-api-0.84.0",
+            ]
+            .map(|it| (format!("/files/{it}.jar"), format!("{it}.jar").into_bytes()));
+            versions
+                .into_iter()
+                .map(|(path, body)| (path.to_owned(), body.to_string().into_bytes()))
+                .chain(files)
+                .collect::<HashMap<_, _>>()
+        })
+        .await
+    }
+
+    /// The only test changing `MODRINTH_API_URL`, so tests running in parallel don't interfere
+    #[tokio::test]
+    async fn resolves_and_installs_from_mock_api() {
+        let server = mock_api().await;
+        env::set_var("MODRINTH_API_URL", &server.url);
+        let directory = temp_dir("modrinth");
+        let downloader = Downloader::new(directory.join("cache")).unwrap();
+        let game = GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Quilt,
+            loader_version: None,
+        };
+
+        let newest = find_version(&downloader, "sodium", None, None, &game)
+            .await
+            .unwrap();
+        assert_eq!(newest.version_number, "0.5.0");
+        let query = server.requests()[0].clone();
+        assert!(query.starts_with("/project/sodium/version?"), "{query}");
+        assert!(
+            query.contains("game_versions=%5B%221.20.1%22%5D"),
+            "{query}"
+        );
+        assert!(
+            query.contains("quilt") && query.contains("fabric"),
+            "{query}"
+        );
+
+        let ranged = find_version(
+            &downloader,
+            "sodium",
+            None,
+            Some(&VersionReq::parse("<0.5").unwrap()),
+            &game,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ranged.version_number, "0.4.10");
+        let exact = find_version(&downloader, "sodium", Some("sodium-0.4.10"), None, &game)
+            .await
+            .unwrap();
+        assert_eq!(exact.id, "sodium-0.4.10");
+        assert!(
+            find_version(&downloader, "sodium", Some("0.6.0"), None, &game)
+                .await
+                .is_err()
+        );
+        assert!(find_version(&downloader, "missing", None, None, &game)
+            .await
+            .is_err());
+
+        let mods = directory.join("mods");
+        let installed = install(&downloader, ranged, true, &game, &mods)
+            .await
+            .unwrap();
+        let projects: Vec<&str> = installed.iter().map(|it| it.project_id.as_str()).collect();
+        // Optional dependencies and the cycle back to sodium are skipped
+        assert_eq!(projects, ["sodium", "indium", "fabric-api"]);
+        for file in lock(&installed).unwrap() {
+            assert_eq!(
+                fs::read_to_string(mods.join(&file.filename)).unwrap(),
+                file.filename
+            );
+        }
+
+        let without_dependencies = directory.join("without_dependencies");
+        let installed = install(&downloader, newest, false, &game, &without_dependencies)
+            .await
+            .unwrap();
+        assert_eq!(installed.len(), 1);
+        assert!(without_dependencies.join("sodium-0.5.0.jar").is_file());
+    }
+
+    #[tokio::test]
+    async fn rejects_vanilla() {
+        let game = GameInfo {
+            version: "1.20.1".to_owned(),
+            loader: instance::Modloader::Vanilla,
+            loader_version: None,
+        };
+        let downloader = Downloader::new(temp_dir("modrinth-vanilla")).unwrap();
+        assert!(find_version(&downloader, "sodium", None, None, &game)
+            .await
+            .is_err());
+    }
+}
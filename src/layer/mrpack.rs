@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use helixlauncher_core::launch::instance;
+use serde::Deserialize;
+
+use super::{
+    archive,
+    download::{Downloader, Hash},
+    GameInfo,
+};
+
+pub const INDEX_FILE: &str = "modrinth.index.json";
+
+#[derive(Deserialize, Debug)]
+pub struct Index {
+    pub files: Vec<IndexFile>,
+    pub dependencies: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IndexFile {
+    pub path: String,
+    pub hashes: IndexHashes,
+    pub env: Option<IndexEnv>,
+    pub downloads: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IndexHashes {
+    pub sha1: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IndexEnv {
+    pub client: String,
+}
+
+impl Index {
+    /// The game version and mod loader declared by the index
+    pub fn game(&self) -> Result<GameInfo> {
+        let version = self
+            .dependencies
+            .get("minecraft")
+            .context("Modpack does not declare a minecraft version")?
+            .clone();
+        let mut loaders = self
+            .dependencies
+            .iter()
+            .filter(|(id, _)| *id != "minecraft");
+        let (loader, loader_version) = match loaders.next() {
+            None => (instance::Modloader::Vanilla, None),
+            Some((id, loader_version)) => (
+                match id.as_str() {
+                    "fabric-loader" => instance::Modloader::Fabric,
+                    "quilt-loader" => instance::Modloader::Quilt,
+                    "forge" => instance::Modloader::Forge,
+                    _ => bail!("Unsupported mod loader {id}"),
+                },
+                Some(loader_version.clone()),
+            ),
+        };
+        ensure!(
+            loaders.next().is_none(),
+            "Modpacks depending on multiple mod loaders are not supported"
+        );
+        Ok(GameInfo {
+            version,
+            loader,
+            loader_version,
+        })
+    }
+
+    /// Installs all client files and overrides of the pack at `pack` into `game_dir`
+    pub async fn install(
+        &self,
+        downloader: &Downloader,
+        pack: &Path,
+        game_dir: &Path,
+    ) -> Result<()> {
+        let files = self
+            .files
+            .iter()
+            .filter(|it| !matches!(&it.env, Some(env) if env.client == "unsupported"));
+        futures::future::try_join_all(files.map(|file| async move {
+            ensure!(
+                Path::new(&file.path)
+                    .components()
+                    .all(|it| matches!(it, Component::Normal(_))),
+                "Modpack file path {} leaves the game directory",
+                file.path
+            );
+            let url = file
+                .downloads
+                .first()
+                .context(format!("No download for {} provided", file.path))?;
+            downloader
+                .download(
+                    url,
+                    &game_dir.join(&file.path),
+                    Some(&Hash::Sha1(file.hashes.sha1.clone())),
+                )
+                .await
+        }))
+        .await?;
+        let mut archive = archive::open(pack)?;
+        archive::extract_dir(&mut archive, "overrides", game_dir)?;
+        archive::extract_dir(&mut archive, "client-overrides", game_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use helixlauncher_core::launch::instance;
+    use serde_json::json;
+    use sha1::Digest;
+
+    use super::Index;
+    use crate::layer::{
+        download::Downloader,
+        test_util::{temp_dir, write_zip, TestServer},
+    };
+
+    fn index(files: serde_json::Value, dependencies: serde_json::Value) -> Index {
+        serde_json::from_value(json!({ "files": files, "dependencies": dependencies })).unwrap()
+    }
+
+    #[test]
+    fn reads_game_from_dependencies() {
+        for (dependencies, loader, loader_version) in [
+            (
+                json!({ "minecraft": "1.20.1" }),
+                instance::Modloader::Vanilla,
+                None,
+            ),
+            (
+                json!({ "minecraft": "1.20.1", "fabric-loader": "0.14.21" }),
+                instance::Modloader::Fabric,
+                Some("0.14.21"),
+            ),
+            (
+                json!({ "minecraft": "1.20.1", "quilt-loader": "0.19.2" }),
+                instance::Modloader::Quilt,
+                Some("0.19.2"),
+            ),
+            (
+                json!({ "minecraft": "1.20.1", "forge": "47.1.0" }),
+                instance::Modloader::Forge,
+                Some("47.1.0"),
+            ),
+        ] {
+            let game = index(json!([]), dependencies).game().unwrap();
+            assert_eq!(game.version, "1.20.1");
+            assert_eq!(game.loader, loader);
+            assert_eq!(game.loader_version.as_deref(), loader_version);
+        }
+        for dependencies in [
+            json!({ "fabric-loader": "0.14.21" }),
+            json!({ "minecraft": "1.20.1", "neoforge": "20.2.86" }),
+            json!({ "minecraft": "1.20.1", "fabric-loader": "0.14.21", "forge": "47.1.0" }),
+        ] {
+            assert!(index(json!([]), dependencies).game().is_err());
+        }
+    }
+
+    fn file(url: &str, path: &str, client: Option<&str>) -> serde_json::Value {
+        let name = path.rsplit('/').next().unwrap();
+        json!({
+            "path": path,
+            "hashes": { "sha1": hex::encode(sha1::Sha1::digest(name)) },
+            "env": client.map(|client| json!({ "client": client, "server": "required" })),
+            "downloads": [format!("{url}/files/{name}")],
+        })
+    }
+
+    #[tokio::test]
+    async fn installs_client_files_and_overrides() {
+        let server = TestServer::start(|_| {
+            ["a.jar", "b.jar", "server.jar", "escape.jar"]
+                .map(|it| (format!("/files/{it}"), it.as_bytes().to_vec()))
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        })
+        .await;
+        let directory = temp_dir("mrpack");
+        let downloader = Downloader::new(directory.join("cache")).unwrap();
+        let pack = directory.join("pack.mrpack");
+        write_zip(
+            &pack,
+            &[
+                ("modrinth.index.json", b"{}"),
+                ("overrides/config/a.toml", b"overrides"),
+                ("overrides/options.txt", b"overrides"),
+                ("client-overrides/options.txt", b"client-overrides"),
+                ("server-overrides/server.properties", b"server-overrides"),
+            ],
+        );
+        let game_dir = directory.join("game");
+        let dependencies = json!({ "minecraft": "1.20.1" });
+        index(
+            json!([
+                file(&server.url, "mods/a.jar", None),
+                file(&server.url, "mods/b.jar", Some("optional")),
+                file(&server.url, "mods/server.jar", Some("unsupported")),
+            ]),
+            dependencies.clone(),
+        )
+        .install(&downloader, &pack, &game_dir)
+        .await
+        .unwrap();
+        for (path, content) in [
+            ("mods/a.jar", "a.jar"),
+            ("mods/b.jar", "b.jar"),
+            ("config/a.toml", "overrides"),
+            ("options.txt", "client-overrides"),
+        ] {
+            assert_eq!(fs::read_to_string(game_dir.join(path)).unwrap(), content);
+        }
+        assert!(!game_dir.join("mods/server.jar").exists());
+        assert!(!game_dir.join("server.properties").exists());
+        assert!(!server.requests().contains(&"/files/server.jar".to_owned()));
+
+        for path in ["../escape.jar", "/tmp/escape.jar", "mods/../../escape.jar"] {
+            let error = index(json!([file(&server.url, path, None)]), dependencies.clone())
+                .install(&downloader, &pack, &directory.join("escaped"))
+                .await
+                .unwrap_err();
+            assert!(
+                error.to_string().contains("leaves the game directory"),
+                "{error:#}"
+            );
+        }
+        assert!(!directory.join("escape.jar").exists());
+    }
+}
@@ -0,0 +1,426 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+fn read_existing(target: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(target) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(format!("Unable to read {}", target.display())),
+    }
+}
+
+pub fn write(target: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(target, content).context(format!("Unable to write {}", target.display()))
+}
+
+/// Sets and removes keys of a `key=value` file like `server.properties`, or `key:value` for `options.txt`.
+/// Comments, ordering and untouched keys are preserved, new keys are appended
+pub fn patch_properties(
+    target: &Path,
+    set: &BTreeMap<String, String>,
+    remove: &[String],
+) -> Result<()> {
+    let separator = if target.file_name().is_some_and(|it| it == "options.txt") {
+        ':'
+    } else {
+        '='
+    };
+    let existing = read_existing(target)?.unwrap_or_default();
+    write(
+        target,
+        &patched_properties(&existing, separator, set, remove),
+    )
+}
+
+fn patched_properties(
+    existing: &str,
+    separator: char,
+    set: &BTreeMap<String, String>,
+    remove: &[String],
+) -> String {
+    let mut remaining = set.clone();
+    let mut lines = vec![];
+    // Whether the previous line continues on this one, and whether that line was dropped
+    let mut continued = false;
+    let mut dropped = false;
+    for line in existing.lines() {
+        let continuation = continued;
+        continued = line.chars().rev().take_while(|it| *it == '\\').count() % 2 == 1;
+        if continuation {
+            if !dropped {
+                lines.push(line.to_owned());
+            }
+            continue;
+        }
+        dropped = false;
+        let Some((key, value_start)) = property_key(line) else {
+            lines.push(line.to_owned());
+            continue;
+        };
+        if remove.contains(&key) {
+            dropped = true;
+            continue;
+        }
+        match remaining.remove(&key) {
+            Some(value) => {
+                // Lines continued by the old value are replaced along with it
+                dropped = true;
+                lines.push(format!("{}{value}", &line[..value_start]));
+            }
+            None => lines.push(line.to_owned()),
+        }
+    }
+    lines.extend(
+        remaining
+            .into_iter()
+            .map(|(key, value)| format!("{key}{separator}{value}")),
+    );
+    lines.join("\n") + "\n"
+}
+
+/// The key of a properties line and the byte offset its value starts at, `None` for blank lines and comments
+/// starting with `#` or `!`. The key ends at the first unescaped `=`, `:` or whitespace, followed by optional
+/// whitespace around at most one `=` or `:`
+fn property_key(line: &str) -> Option<(String, usize)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with(['#', '!']) {
+        return None;
+    }
+    let mut key = String::new();
+    let mut end = trimmed.len();
+    let mut chars = trimmed.char_indices();
+    while let Some((index, char)) = chars.next() {
+        match char {
+            '\\' => key.extend(chars.next().map(|(_, escaped)| escaped)),
+            '=' | ':' => {
+                end = index;
+                break;
+            }
+            char if char.is_whitespace() => {
+                end = index;
+                break;
+            }
+            char => key.push(char),
+        }
+    }
+    let rest = trimmed[end..].trim_start();
+    let value = match rest.strip_prefix(['=', ':']) {
+        Some(value) => value.trim_start(),
+        None => rest,
+    };
+    Some((key, line.len() - value.len()))
+}
+
+/// Sets and removes values of a json or toml file by dot separated key paths, e.g. `client.render.fancy`.
+/// Key order, and for toml comments and formatting, are preserved
+pub fn patch_config(target: &Path, set: &BTreeMap<String, Value>, remove: &[String]) -> Result<()> {
+    let existing = read_existing(target)?;
+    let content = if target.extension().is_some_and(|it| it == "toml") {
+        patched_toml(existing.as_deref().unwrap_or_default(), set, remove)
+            .context(format!("Unable to patch {}", target.display()))?
+    } else {
+        patched_json(existing.as_deref(), set, remove)
+            .context(format!("Unable to patch {}", target.display()))?
+    };
+    write(target, &content)
+}
+
+fn patched_json(
+    existing: Option<&str>,
+    set: &BTreeMap<String, Value>,
+    remove: &[String],
+) -> Result<String> {
+    let mut document = match existing {
+        None => Value::Object(Default::default()),
+        Some(content) => serde_json::from_str(content).context("Invalid json")?,
+    };
+    for (path, value) in set {
+        *value_at(&mut document, path)? = value.clone();
+    }
+    for path in remove {
+        remove_at(&mut document, path)?;
+    }
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+fn patched_toml(
+    existing: &str,
+    set: &BTreeMap<String, Value>,
+    remove: &[String],
+) -> Result<String> {
+    let mut document: toml_edit::Document = existing.parse().context("Invalid toml")?;
+    let root = document.as_item_mut();
+    for (path, value) in set {
+        let item = toml_item_at(root, path)?;
+        *item = match toml_value(value)? {
+            // Keep tables written as sections instead of turning them into inline tables
+            toml_edit::Value::InlineTable(table) if item.is_table() => {
+                toml_edit::Item::Table(table.into_table())
+            }
+            value => toml_edit::value(value),
+        };
+    }
+    for path in remove {
+        let (parent, key) = match path.rsplit_once('.') {
+            Some((parent, key)) => (toml_existing_item(root, parent), key),
+            None => (Some(&mut *root), path.as_str()),
+        };
+        let Some(parent) = parent else {
+            continue;
+        };
+        match (key.parse::<usize>(), parent) {
+            (Ok(index), toml_edit::Item::ArrayOfTables(array)) if index < array.len() => {
+                array.remove(index)
+            }
+            (Ok(index), toml_edit::Item::Value(toml_edit::Value::Array(array)))
+                if index < array.len() =>
+            {
+                array.remove(index);
+                // The new first element would keep the space after the former one's comma
+                if let Some(first) = array.get_mut(0).filter(|_| index == 0) {
+                    first.decor_mut().set_prefix("");
+                }
+            }
+            (_, parent) => {
+                if let Some(table) = parent.as_table_like_mut() {
+                    table.remove(key);
+                }
+            }
+        }
+    }
+    Ok(document.to_string())
+}
+
+fn is_toml_array(item: &toml_edit::Item) -> bool {
+    item.is_array() || item.is_array_of_tables()
+}
+
+/// The toml item at `path`, creating missing tables on the way
+fn toml_item_at<'a>(
+    document: &'a mut toml_edit::Item,
+    path: &str,
+) -> Result<&'a mut toml_edit::Item> {
+    let mut current = document;
+    for key in path.split('.') {
+        current = match key.parse::<usize>() {
+            Ok(index) if is_toml_array(current) => current
+                .get_mut(index)
+                .context(format!("Index {index} in `{path}` is out of bounds"))?,
+            _ => current
+                .get_mut(key)
+                .context(format!("`{path}` does not lead through a table"))?,
+        };
+    }
+    Ok(current)
+}
+
+/// The toml item at `path` if it exists
+fn toml_existing_item<'a>(
+    document: &'a mut toml_edit::Item,
+    path: &str,
+) -> Option<&'a mut toml_edit::Item> {
+    let mut current = document;
+    for key in path.split('.') {
+        current = match key.parse::<usize>() {
+            Ok(index) if is_toml_array(current) => current.get_mut(index)?,
+            _ => {
+                current.get(key)?;
+                current.get_mut(key)?
+            }
+        };
+    }
+    Some(current)
+}
+
+fn toml_value(value: &Value) -> Result<toml_edit::Value> {
+    Ok(match value {
+        Value::Null => bail!("Toml has no null value, remove the key instead"),
+        Value::Bool(value) => (*value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => integer.into(),
+            None => number
+                .as_f64()
+                .context(format!("{number} can't be represented as toml"))?
+                .into(),
+        },
+        Value::String(value) => value.as_str().into(),
+        Value::Array(values) => {
+            toml_edit::Value::Array(values.iter().map(toml_value).collect::<Result<_>>()?)
+        }
+        Value::Object(map) => toml_edit::Value::InlineTable(
+            map.iter()
+                .map(|(key, value)| Ok((key.as_str(), toml_value(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+/// The value at `path`, creating missing objects on the way
+fn value_at<'a>(document: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    let mut current = document;
+    for key in path.split('.') {
+        if current.is_null() {
+            *current = Value::Object(Default::default());
+        }
+        current = match current {
+            Value::Object(map) => map.entry(key).or_insert(Value::Null),
+            Value::Array(array) => {
+                let index: usize = key
+                    .parse()
+                    .context(format!("`{key}` in `{path}` is not an array index"))?;
+                array
+                    .get_mut(index)
+                    .context(format!("Index {index} in `{path}` is out of bounds"))?
+            }
+            _ => bail!("`{path}` does not lead through an object"),
+        };
+    }
+    Ok(current)
+}
+
+fn remove_at(document: &mut Value, path: &str) -> Result<()> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (value_at(document, parent)?, key),
+        None => (document, path),
+    };
+    match parent {
+        Value::Object(map) => {
+            map.remove(key);
+        }
+        Value::Array(array) => {
+            if let Ok(index) = key.parse::<usize>() {
+                if index < array.len() {
+                    array.remove(index);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::{json, Value};
+
+    use super::{patched_json, patched_properties, patched_toml, property_key};
+
+    fn set<V: Clone>(entries: &[(&str, V)]) -> BTreeMap<String, V> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_property_keys() {
+        for (line, expected) in [
+            ("key=value", Some(("key", "value"))),
+            ("key = value", Some(("key", "value"))),
+            ("key:value", Some(("key", "value"))),
+            ("key value", Some(("key", "value"))),
+            ("  key\t:  value", Some(("key", "value"))),
+            ("key=", Some(("key", ""))),
+            ("key", Some(("key", ""))),
+            ("key=a=b", Some(("key", "a=b"))),
+            (
+                "key_key.attack:key.mouse.left",
+                Some(("key_key.attack", "key.mouse.left")),
+            ),
+            (r"a\=b\ c=value", Some(("a=b c", "value"))),
+            ("# comment=value", None),
+            ("! comment", None),
+            ("   # indented comment", None),
+            ("   ", None),
+        ] {
+            let parsed = property_key(line).map(|(key, start)| (key, &line[start..]));
+            assert_eq!(
+                parsed,
+                expected.map(|(key, value)| (key.to_owned(), value)),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn patches_properties() {
+        let existing = "# generated\n! also a comment=1\n  motd = A server\nonline-mode:true\nlong=a\\\n  b\\\n  c\nkept=\\\n  x\nremoved=1\n";
+        let patched = patched_properties(
+            existing,
+            '=',
+            &set(&[
+                ("motd", "Test".to_owned()),
+                ("online-mode", "false".to_owned()),
+                ("long", "short".to_owned()),
+                ("new", "value".to_owned()),
+            ]),
+            &["removed".to_owned(), "comment".to_owned()],
+        );
+        assert_eq!(
+            patched,
+            "# generated\n! also a comment=1\n  motd = Test\nonline-mode:false\nlong=short\nkept=\\\n  x\nnew=value\n"
+        );
+        assert_eq!(
+            patched_properties("", ':', &set(&[("fov", "1.0".to_owned())]), &[]),
+            "fov:1.0\n"
+        );
+    }
+
+    #[test]
+    fn patches_json_keeping_key_order() {
+        let existing = r#"{"zeta": 1, "alpha": {"list": [1, 2, 3], "b": true}, "removed": 0}"#;
+        let patched = patched_json(
+            Some(existing),
+            &set(&[
+                ("alpha.b", json!(false)),
+                ("alpha.list.1", json!(5)),
+                ("new.nested", json!("x")),
+            ]),
+            &["removed".to_owned(), "alpha.list.0".to_owned()],
+        )
+        .unwrap();
+        let keys: Vec<String> = serde_json::from_str::<serde_json::Map<String, Value>>(&patched)
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(keys, ["zeta", "alpha", "new"]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&patched).unwrap(),
+            json!({"zeta": 1, "alpha": {"list": [5, 3], "b": false}, "new": {"nested": "x"}})
+        );
+        assert!(patched_json(Some("{"), &set(&[]), &[]).is_err());
+        assert!(patched_json(None, &set(&[("a.0", json!(1))]), &[]).is_ok());
+        assert!(patched_json(Some("[1]"), &set(&[("3", json!(1))]), &[]).is_err());
+    }
+
+    #[test]
+    fn patches_toml_keeping_formatting() {
+        let existing = "# Client settings\n[client]\n# Render fancy graphics\nfancy = true\ncreated = 1979-05-27T07:32:00Z\nlist = [1, 2, 3]\n\n[server]\nport = 25565 # default\n";
+        let patched = patched_toml(
+            existing,
+            &set(&[
+                ("client.fancy", json!(false)),
+                ("client.list.2", json!(4)),
+                ("server.motd", json!("Test")),
+                ("other.nested.value", json!(1.5)),
+            ]),
+            &["client.list.0".to_owned(), "missing.key".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(
+            patched,
+            "other = { nested = { value = 1.5 } }\n# Client settings\n[client]\n# Render fancy graphics\nfancy = false\ncreated = 1979-05-27T07:32:00Z\nlist = [2, 4]\n\n[server]\nport = 25565 # default\nmotd = \"Test\"\n"
+        );
+        assert!(patched_toml("", &set(&[("a", Value::Null)]), &[]).is_err());
+        assert!(patched_toml("a = 1", &set(&[("a.b", json!(1))]), &[]).is_err());
+        assert!(patched_toml("a = [", &set(&[]), &[]).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+
+/// Replaces `${name}` in `input` by the value `lookup` returns for `name`. `$${` is kept as a literal `${`,
+/// e.g. for shell variables in commands
+pub fn substitute(input: &str, lookup: &impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let end = variable
+                .find('}')
+                .context(format!("Unclosed variable in `{input}`"))?;
+            output.push_str(&lookup(&variable[..end])?);
+            rest = &variable[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Substitutes variables in all strings inside a json value, object keys are left unchanged
+pub fn substitute_all(
+    value: &mut serde_json::Value,
+    lookup: &impl Fn(&str) -> Result<String>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(string) if string.contains('$') => {
+            *string = substitute(string, lookup)?;
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                substitute_all(value, lookup)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for value in map.values_mut() {
+                substitute_all(value, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{bail, Result};
+    use serde_json::json;
+
+    use super::{substitute, substitute_all};
+
+    fn lookup(name: &str) -> Result<String> {
+        Ok(match name {
+            "version" => "1.20.1".to_owned(),
+            "empty" => String::new(),
+            name => bail!("Unknown variable `${{{name}}}`"),
+        })
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        for (input, expected) in [
+            ("mods-${version}.zip", "mods-1.20.1.zip"),
+            ("${version}${version}", "1.20.11.20.1"),
+            ("a${empty}b", "ab"),
+            ("cp $${HOME}/mods .", "cp ${HOME}/mods ."),
+            ("echo $${version}", "echo ${version}"),
+            ("cost $5, $HOME", "cost $5, $HOME"),
+            ("trailing $", "trailing $"),
+            ("", ""),
+        ] {
+            assert_eq!(substitute(input, &lookup).unwrap(), expected, "{input}");
+        }
+        assert!(substitute("${version", &lookup).is_err());
+        let error = substitute("cp ${HOME}/mods .", &lookup).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown variable `${HOME}`");
+    }
+
+    #[test]
+    fn substitutes_strings_in_values() {
+        let mut value = json!({
+            "target": "mods/${version}.jar",
+            "${version}": ["${version}", 1, null, { "nested": "$${version}" }],
+        });
+        substitute_all(&mut value, &lookup).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "target": "mods/1.20.1.jar",
+                "${version}": ["1.20.1", 1, null, { "nested": "${version}" }],
+            })
+        );
+        assert!(substitute_all(&mut json!(["${unknown}"]), &lookup).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Helpers for tests of layers which talk to remote apis or write files
+use std::{
+    collections::HashMap,
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Serves fixed responses by path, the query is ignored for routing
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Starts serving the routes `routes` builds from the server url on a free local port.
+    /// Unknown paths are answered with 404
+    pub async fn start(routes: impl FnOnce(&str) -> HashMap<String, Vec<u8>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(routes(&url));
+        let requests = Arc::new(Mutex::new(vec![]));
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let routes = routes.clone();
+                    let requests = requests.clone();
+                    tokio::spawn(async move {
+                        let mut request = vec![];
+                        let mut buffer = [0; 1024];
+                        // Reads the head and the body, so closing the connection doesn't reset it
+                        while !is_complete(&request) {
+                            match stream.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(read) => request.extend_from_slice(&buffer[..read]),
+                            }
+                        }
+                        let request = String::from_utf8_lossy(&request);
+                        let target = request.split_whitespace().nth(1).unwrap_or_default();
+                        requests.lock().unwrap().push(target.to_owned());
+                        let path = target.split('?').next().unwrap_or_default();
+                        let (status, body) = match routes.get(path) {
+                            Some(body) => ("200 OK", body.as_slice()),
+                            None => ("404 Not Found", &[][..]),
+                        };
+                        let head = format!(
+                            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        );
+                        stream.write_all(head.as_bytes()).await.ok();
+                        stream.write_all(body).await.ok();
+                    });
+                }
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// The paths, including the query, requested so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Whether `request` contains the full head and as much of the body as its `Content-Length` declares
+fn is_complete(request: &[u8]) -> bool {
+    let Some(head_end) = request.windows(4).position(|it| it == b"\r\n\r\n") else {
+        return false;
+    };
+    let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+    let body_length = head
+        .lines()
+        .find_map(|it| it.strip_prefix("content-length:"))
+        .and_then(|it| it.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    request.len() >= head_end + 4 + body_length
+}
+
+/// Writes a zip archive with the `files` given by path and content to `path`
+pub fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+/// An empty directory unique to the test `name`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("mc-prod-test-{}-{name}", std::process::id()));
+    std::fs::remove_dir_all(&directory).ok();
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Concrete versions chosen for version queries, stored in `profiles.lock.json` so runs are reproducible
+#[derive(Serialize, Deserialize, Default)]
+pub struct LockFile {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileLock>,
+
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ProfileLock {
+    /// Minecraft versions each version range expanded to
+    #[serde(default)]
+    pub version_ranges: BTreeMap<String, Vec<String>>,
+    /// Concrete loader versions, keyed by loader, game version and query
+    #[serde(default)]
+    pub loader_versions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub variants: BTreeMap<String, LockedVariant>,
+}
+
+/// The versions a variant was set up with
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct LockedVariant {
+    pub game_version: Option<String>,
+    pub loader: Option<String>,
+    pub loader_version: Option<String>,
+    /// Files installed for each Modrinth mod layer, including resolved dependencies.
+    /// Keyed by the project and the requested version
+    #[serde(default)]
+    pub mods: BTreeMap<String, Vec<LockedFile>>,
+    /// Files chosen for CurseForge layers which don't name a file, keyed by `mod <id>` or `pack <id>`
+    #[serde(default)]
+    pub curseforge: BTreeMap<String, LockedFile>,
+    /// Builds chosen for `latest` and `-SNAPSHOT` maven artifacts, keyed by repository and coordinates
+    #[serde(default)]
+    pub maven: BTreeMap<String, LockedFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LockedFile {
+    pub project_id: String,
+    pub version_id: String,
+    pub filename: String,
+    pub url: String,
+    pub sha1: String,
+}
+
+impl LockFile {
+    pub fn read_or_default(path: PathBuf) -> Result<Self> {
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let mut it: Self =
+                    serde_json::from_str(&content).context("Lock file format invalid")?;
+                it.path = path;
+                Ok(it)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self {
+                profiles: BTreeMap::new(),
+                path,
+            }),
+            Err(e) => Err(e).context("Could not read lock file"),
+        }
+    }
+
+    pub fn safe(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(self).unwrap())
+            .context("Error saving lock file")
+    }
+}
@@ -1,12 +1,20 @@
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
 
 mod command;
 mod config;
 pub mod layer;
+mod lock;
+mod report;
+mod version;
 
 use anyhow::{Context, Ok, Result};
 use clap::{Parser, Subcommand};
-use helixlauncher_core::auth::account;
+use helixlauncher_core::{auth::account, launch::instance};
+use layer::parse_modloader;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -14,18 +22,119 @@ async fn main() -> Result<()> {
     let profile_dir = args.profile_dir.map_or_else(||env::current_dir().context("No working directory provided by environment, provide a profile directory using --profile_dir"), |it|->Result<PathBuf>{Ok(it)})?;
 
     let mut profile_config =
-        config::ProfileConfig::read_or_create(profile_dir.join("profiles.json"))?;
+        match config::ProfileConfig::read_or_create(config::ProfileConfig::find(&profile_dir)?) {
+            Result::Ok(config) => config,
+            // Validating reports errors reading the config like other problems
+            Err(e) => match args.subcommand {
+                Commands::Profile {
+                    command: ProfileCommands::Validate { name },
+                } => return command::profile::validate(name, Err(e)).await,
+                _ => return Err(e),
+            },
+        };
+    let lock = lock::LockFile::read_or_default(profile_dir.join("profiles.lock.json"))?;
     let mut account_config = account::AccountConfig::new(profile_dir.join("accounts.json"))?;
+    let interactive =
+        !args.non_interactive && io::stdin().is_terminal() && env::var_os("CI").is_none();
     return match args.subcommand {
         Commands::Profile {
-            command: ProfileCommands::Run { name },
-        } => command::profile::run(name, profile_config, account_config).await,
+            command: ProfileCommands::Run { name, filters },
+        } => {
+            command::profile::run(
+                name,
+                &filters,
+                profile_config,
+                lock,
+                account_config,
+                args.offline,
+                interactive,
+            )
+            .await
+        }
+        Commands::Profile {
+            command: ProfileCommands::Plan { name, filters },
+        } => {
+            command::profile::plan(
+                name,
+                &filters,
+                profile_config,
+                lock,
+                args.offline,
+                interactive,
+            )
+            .await
+        }
+        Commands::Profile {
+            command: ProfileCommands::Update { name },
+        } => command::profile::update(name, profile_config, lock, args.offline).await,
         Commands::Profile {
-            command: ProfileCommands::Create { name },
-        } => command::profile::create(name, &mut profile_config).await,
+            command: ProfileCommands::Validate { name },
+        } => command::profile::validate(name, Ok(profile_config)).await,
+        Commands::Profile {
+            command: ProfileCommands::Convert { format },
+        } => command::profile::convert(format, profile_config).await,
+        Commands::Profile {
+            command:
+                ProfileCommands::Create {
+                    name,
+                    version,
+                    loader,
+                    loader_version,
+                },
+        } => {
+            let options = command::profile::CreateOptions {
+                version,
+                loader,
+                loader_version,
+                offline: args.offline,
+                interactive,
+            };
+            command::profile::create(name, &mut profile_config, options).await
+        }
         Commands::Profile {
             command: ProfileCommands::Switch { name },
-        } => command::profile::switch(name, &mut profile_config).await,
+        } => command::profile::switch(name, &mut profile_config, interactive).await,
+        Commands::Profile {
+            command: ProfileCommands::List,
+        } => command::profile::list(profile_config, lock).await,
+        Commands::Profile {
+            command: ProfileCommands::Show { name, resolved },
+        } => command::profile::show(name, resolved, profile_config).await,
+        Commands::Profile {
+            command: ProfileCommands::Delete { name },
+        } => command::profile::delete(name, profile_config, lock).await,
+        Commands::Profile {
+            command: ProfileCommands::Rename { name, new_name },
+        } => command::profile::rename(name, new_name, profile_config, lock).await,
+        Commands::Profile {
+            command: ProfileCommands::Copy { name, new_name },
+        } => command::profile::copy(name, new_name, profile_config).await,
+        Commands::Profile {
+            command: ProfileCommands::Import { path, name, entry },
+        } => command::profile::import(&path, name, entry, &mut profile_config, interactive).await,
+        Commands::Profile {
+            command: ProfileCommands::Edit { name },
+        } => command::profile::edit(name, profile_config, interactive).await,
+        Commands::Profile {
+            command:
+                ProfileCommands::Export {
+                    name,
+                    variant,
+                    pack_version,
+                    format,
+                    output,
+                },
+        } => {
+            let options = command::profile::ExportOptions {
+                variant,
+                pack_version,
+                format,
+                output,
+                offline: args.offline,
+                interactive,
+            };
+            command::profile::export(name, profile_config, lock, options).await
+        }
         Commands::Account {
             command: AccountCommands::List,
         } => command::account::list(&account_config).await,
@@ -39,8 +148,7 @@ async fn main() -> Result<()> {
         Commands::Schema => {
             println!(
                 "{}",
-                serde_json::to_string_pretty(&schemars::schema_for!(config::ProfileConfig))
-                    .unwrap()
+                serde_json::to_string_pretty(&config::ProfileConfig::json_schema()).unwrap()
             );
             Ok(())
         }
@@ -59,6 +167,12 @@ struct McProdTest {
     pub max_running_profiles: usize,
     #[clap(long, short)]
     pub profile_dir: Option<PathBuf>,
+    /// Avoid network access where possible, e.g. by running gradle builds offline
+    #[clap(long)]
+    pub offline: bool,
+    /// Fail instead of prompting for missing input. Enabled if stdin is not a terminal or `CI` is set
+    #[clap(long)]
+    pub non_interactive: bool,
 }
 
 #[derive(Subcommand)]
@@ -73,7 +187,7 @@ enum Commands {
         #[clap(subcommand)]
         command: AccountCommands,
     },
-    /// Prints a json schema for the profile json to stdout
+    /// Prints a json schema for the profile config to stdout, which applies to json, yaml and toml configs
     Schema,
 }
 
@@ -92,11 +206,91 @@ enum ProfileCommands {
     Run {
         /// The name of the profile which is ran. This will take precedence over the selected profile
         name: Option<String>,
+        /// Only run variants with the given matrix axis value, as `axis=value`
+        #[clap(long = "filter")]
+        filters: Vec<String>,
+    },
+    /// Print the variants of the given profile with all versions resolved, prompts if none is given
+    #[clap(alias("p"))]
+    Plan {
+        /// The name of the profile to plan. This will take precedence over the selected profile
+        name: Option<String>,
+        /// Only print variants with the given matrix axis value, as `axis=value`
+        #[clap(long = "filter")]
+        filters: Vec<String>,
+    },
+    /// Resolve the versions locked in profiles.lock.json again, for all profiles if none is given
+    #[clap(alias("u"))]
+    Update { name: Option<String> },
+    /// Check the given profile, or all profiles if none is given, for problems that would only show up when running it
+    Validate { name: Option<String> },
+    /// Rewrite the profile config in another format, comments are not preserved
+    Convert {
+        #[clap(value_enum)]
+        format: config::ConfigFormat,
     },
     /// Create a new profile
     #[clap(alias("add"), alias("new"), alias("a"), alias("n"), alias("c"))]
-    Create { name: Option<String> },
+    Create {
+        name: Option<String>,
+        /// The minecraft version of the instance layer, no layers are prompted for if given
+        #[clap(long)]
+        version: Option<String>,
+        /// The mod loader of the instance layer, defaults to vanilla
+        #[clap(long, requires = "version", value_parser = parse_modloader)]
+        loader: Option<instance::Modloader>,
+        /// The mod loader version, also accepting queries like `>=0.14`. Defaults to `latest-stable`
+        #[clap(long, requires = "loader")]
+        loader_version: Option<String>,
+    },
     /// Select the default profile executed when run is used
     #[clap(alias("select"), alias("s"))]
     Switch { name: Option<String> },
+    /// List all profiles with their number of variants, the selected profile is marked with `*`
+    #[clap(alias("ls"))]
+    List,
+    /// Print the layers of a profile
+    Show {
+        name: String,
+        /// Include the layers of extended profiles and fragments
+        #[clap(long)]
+        resolved: bool,
+    },
+    /// Delete a profile, its variant directories are kept
+    #[clap(alias("rm"))]
+    Delete { name: String },
+    /// Rename a profile, moving its variant directories as well
+    #[clap(alias("mv"))]
+    Rename { name: String, new_name: String },
+    /// Copy a profile under a new name
+    #[clap(alias("cp"))]
+    Copy { name: String, new_name: String },
+    /// Open a profile in $EDITOR, invalid edits are not saved and edits with problems only after confirmation
+    Edit { name: String },
+    /// Create a profile from a MultiMC or Prism instance directory, or from a profile of the vanilla launcher
+    Import {
+        path: PathBuf,
+        /// The name of the new profile, defaults to the name of the instance
+        #[clap(long)]
+        name: Option<String>,
+        /// The vanilla launcher profile to import, prompts if there are multiple
+        #[clap(long)]
+        entry: Option<String>,
+    },
+    /// Set up a variant and package it as a modpack or instance, without logs, caches and account data
+    Export {
+        /// The profile to export, falls back to the selected profile
+        name: Option<String>,
+        /// The variant to export, prompts if the profile has multiple
+        #[clap(long)]
+        variant: Option<String>,
+        /// The version of the Modrinth modpack, defaults to the variant name
+        #[clap(long)]
+        pack_version: Option<String>,
+        #[clap(long, value_enum, default_value = "mrpack")]
+        format: command::export::ExportFormat,
+        /// The archive to write, defaults to `exports/<variant>.<extension>` in the profile directory
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
 }
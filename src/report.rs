@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::layer::JavaRuntime;
+
+/// Summary of a profile run, written to `report.json` inside the profile directory
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub profile: String,
+    pub variants: Vec<VariantReport>,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct VariantReport {
+    pub name: String,
+    pub game_version: Option<String>,
+    pub loader: Option<String>,
+    pub loader_version: Option<String>,
+    pub java: Option<JavaRuntime>,
+    /// Whether the game exited successfully, `None` if it was not launched
+    pub success: Option<bool>,
+}
+
+impl Report {
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        fs::create_dir_all(directory)?;
+        fs::write(
+            directory.join("report.json"),
+            serde_json::to_string_pretty(self).unwrap(),
+        )
+        .context("Unable to write run report")
+    }
+}
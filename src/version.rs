@@ -0,0 +1,233 @@
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+
+/// Compares two loosely formatted version strings, e.g. `1.20.1`, `0.14.21+1.20` or `1.20-pre1`.
+///
+/// Numeric parts are compared numerically and missing ones count as zero, so `1.20` equals `1.20.0`.
+/// A version with a trailing non-numeric part (`-pre1`, `-rc2`, `-beta`) is considered older than the version without it.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let a = tokens(a);
+    let b = tokens(b);
+    for index in 0..a.len().max(b.len()) {
+        let ordering = match (a.get(index), b.get(index)) {
+            (Some(Token::Number(a)), Some(Token::Number(b))) => a.cmp(b),
+            (Some(Token::Text(a)), Some(Token::Text(b))) => a.cmp(b),
+            (Some(Token::Number(_)), Some(Token::Text(_))) => Ordering::Greater,
+            (Some(Token::Text(_)), Some(Token::Number(_))) => Ordering::Less,
+            (Some(Token::Number(a)), None) => a.cmp(&0),
+            (Some(Token::Text(_)), None) => Ordering::Less,
+            (None, Some(Token::Number(b))) => 0.cmp(b),
+            (None, Some(Token::Text(_))) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+#[derive(PartialEq, Eq)]
+enum Token {
+    Number(u64),
+    Text(String),
+}
+
+fn tokens(version: &str) -> Vec<Token> {
+    // Build metadata does not take part in ordering
+    let version = version.split('+').next().unwrap_or_default();
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut numeric = false;
+    for char in version.chars() {
+        if !char.is_ascii_alphanumeric() {
+            push_token(&mut tokens, &mut current, numeric);
+            continue;
+        }
+        if !current.is_empty() && char.is_ascii_digit() != numeric {
+            push_token(&mut tokens, &mut current, numeric);
+        }
+        numeric = char.is_ascii_digit();
+        current.push(char);
+    }
+    push_token(&mut tokens, &mut current, numeric);
+    tokens
+}
+
+fn push_token(tokens: &mut Vec<Token>, current: &mut String, numeric: bool) {
+    if current.is_empty() {
+        return;
+    }
+    tokens.push(if numeric {
+        Token::Number(current.parse().unwrap_or(u64::MAX))
+    } else {
+        Token::Text(current.to_lowercase())
+    });
+    current.clear();
+}
+
+/// A requirement on a version, parsed from strings like `>=1.20`, `>=0.5, <0.6`,
+/// `1.20.x`, `1.19.2..=1.20.1` or an exact version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionReq {
+    comparators: Vec<Comparator>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Comparator {
+    Exact(String),
+    Greater(String),
+    GreaterEq(String),
+    Less(String),
+    LessEq(String),
+    Prefix(String),
+}
+
+impl VersionReq {
+    pub fn parse(requirement: &str) -> Result<Self> {
+        let mut comparators = vec![];
+        for part in requirement.split(',').map(str::trim) {
+            if part.is_empty() {
+                bail!("Empty version requirement in `{requirement}`");
+            }
+            if let Some((from, to)) = part.split_once("..=") {
+                comparators.extend(range_bounds(from, to, Comparator::LessEq));
+            } else if let Some((from, to)) = part.split_once("..") {
+                comparators.extend(range_bounds(from, to, Comparator::Less));
+            } else if let Some(version) = part.strip_prefix(">=") {
+                comparators.push(Comparator::GreaterEq(version.trim().to_owned()));
+            } else if let Some(version) = part.strip_prefix("<=") {
+                comparators.push(Comparator::LessEq(version.trim().to_owned()));
+            } else if let Some(version) = part.strip_prefix('>') {
+                comparators.push(Comparator::Greater(version.trim().to_owned()));
+            } else if let Some(version) = part.strip_prefix('<') {
+                comparators.push(Comparator::Less(version.trim().to_owned()));
+            } else if let Some(version) = part.strip_prefix('=') {
+                comparators.push(Comparator::Exact(version.trim().to_owned()));
+            } else if let Some(prefix) = part.strip_suffix(".x").or_else(|| part.strip_suffix(".*"))
+            {
+                comparators.push(Comparator::Prefix(prefix.to_owned()));
+            } else {
+                comparators.push(Comparator::Exact(part.to_owned()));
+            }
+        }
+        Ok(Self { comparators })
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        self.matches_with(version, compare)
+    }
+
+    /// Like [`Self::matches`], but ordering versions using `compare`
+    pub fn matches_with(&self, version: &str, compare: impl Fn(&str, &str) -> Ordering) -> bool {
+        self.comparators.iter().all(|it| match it {
+            Comparator::Exact(other) => compare(version, other) == Ordering::Equal,
+            Comparator::Greater(other) => compare(version, other) == Ordering::Greater,
+            Comparator::GreaterEq(other) => compare(version, other) != Ordering::Less,
+            Comparator::Less(other) => compare(version, other) == Ordering::Less,
+            Comparator::LessEq(other) => compare(version, other) != Ordering::Greater,
+            Comparator::Prefix(prefix) => {
+                version == prefix
+                    || version
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            }
+        })
+    }
+}
+
+fn range_bounds(from: &str, to: &str, upper: fn(String) -> Comparator) -> Vec<Comparator> {
+    let mut bounds = vec![];
+    if !from.trim().is_empty() {
+        bounds.push(Comparator::GreaterEq(from.trim().to_owned()));
+    }
+    if !to.trim().is_empty() {
+        bounds.push(upper(to.trim().to_owned()));
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{compare, VersionReq};
+
+    #[test]
+    fn compares_versions() {
+        for (a, b, expected) in [
+            ("1.20.1", "1.20.1", Ordering::Equal),
+            ("1.20", "1.20.0", Ordering::Equal),
+            ("1.20.0.0", "1.20", Ordering::Equal),
+            ("1.20", "1.20.1", Ordering::Less),
+            ("1.9", "1.10", Ordering::Less),
+            ("1.20.10", "1.20.9", Ordering::Greater),
+            ("1.20-pre1", "1.20", Ordering::Less),
+            ("1.20-pre1", "1.20-pre2", Ordering::Less),
+            ("1.20-pre2", "1.20-rc1", Ordering::Less),
+            ("1.20-rc1", "1.20", Ordering::Less),
+            ("1.20-rc1", "1.20.1", Ordering::Less),
+            ("0.14.21+1.20", "0.14.21+1.19", Ordering::Equal),
+            ("0.14.21+build.3", "0.14.22", Ordering::Less),
+            ("1.20-BETA", "1.20-beta", Ordering::Equal),
+            ("23w31a", "23w31b", Ordering::Less),
+            ("23w31a", "23w45a", Ordering::Less),
+        ] {
+            assert_eq!(compare(a, b), expected, "{a} vs {b}");
+            assert_eq!(compare(b, a), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn matches_requirements() {
+        for (requirement, version, expected) in [
+            ("1.20.1", "1.20.1", true),
+            ("1.20", "1.20.0", true),
+            ("=1.20.1", "1.20.2", false),
+            (">=1.20", "1.20", true),
+            (">=1.20", "1.20-pre1", false),
+            (">=1.20", "1.20.1", true),
+            (">1.20", "1.20.0", false),
+            ("<1.20", "1.20-rc1", true),
+            ("<=1.20", "1.20.0", true),
+            (">=0.5, <0.6", "0.5.3", true),
+            (">=0.5, <0.6", "0.6", false),
+            ("1.20.x", "1.20", true),
+            ("1.20.x", "1.20.4", true),
+            ("1.20.*", "1.20.4", true),
+            ("1.20.x", "1.200", false),
+            ("1.20.x", "1.21", false),
+            ("1.19.2..=1.20.1", "1.20.1", true),
+            ("1.19.2..=1.20.1", "1.19.1", false),
+            ("1.19.2..1.20.1", "1.20.1", false),
+            ("..1.20", "1.19.4", true),
+            ("1.20..", "1.21", true),
+        ] {
+            assert_eq!(
+                VersionReq::parse(requirement).unwrap().matches(version),
+                expected,
+                "{version} in {requirement}"
+            );
+        }
+    }
+
+    #[test]
+    fn matches_with_custom_order() {
+        // Snapshots can't be ordered by their name, so game versions are ordered by the version manifest
+        let manifest = ["1.20", "23w31a", "1.20.1", "1.20.2"];
+        let position = |version: &str| manifest.iter().position(|it| *it == version);
+        let requirement = VersionReq::parse(">1.20, <1.20.2").unwrap();
+        let matching: Vec<&str> = manifest
+            .into_iter()
+            .filter(|it| requirement.matches_with(it, |a, b| position(a).cmp(&position(b))))
+            .collect();
+        assert_eq!(matching, ["23w31a", "1.20.1"]);
+    }
+
+    #[test]
+    fn rejects_empty_requirements() {
+        assert!(VersionReq::parse("").is_err());
+        assert!(VersionReq::parse(">=1.20,").is_err());
+    }
+}